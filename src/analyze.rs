@@ -0,0 +1,391 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::{
+    detect::{FileType, get_file_type},
+    dump::{HeaderDump, dump_hdr, dump_snd},
+    file::{self, DirEntry, File, FileSystem},
+    hdr::{HDR_LEN, decode_hdr},
+    snd::{SND_HEADER_LEN_WITH_ENCRYPTION, decode_snd_header},
+    unionfile::UnionFileSystem,
+};
+
+/// When the most common value of an offset is in at least this share of the
+/// headers, the headers with another value are outliers
+const OUTLIER_MODE_SHARE: f64 = 0.9;
+
+/// Values shown for each offset, the CSV export has all of them
+const MAX_PRINTED_VALUES: usize = 4;
+const MAX_PRINTED_OUTLIERS: usize = 10;
+
+/// A header found in the library, with what its values are compared to
+struct Sample {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    /// Field of each byte, as shown by dump
+    fields: Vec<String>,
+    /// HDR files take the revision of their SND file
+    revision: String,
+    studio: String,
+    id: u16,
+    reel: u8,
+}
+
+impl Sample {
+    fn new(
+        path: &Path,
+        dump: HeaderDump,
+        bytes: &[u8],
+        studio: Option<String>,
+        id: u16,
+        reel: u8,
+    ) -> Self {
+        let mut fields = vec![String::new(); dump.len];
+        for f in &dump.fields {
+            fields[f.start..f.end].fill(f.name.clone());
+        }
+        Sample {
+            path: path.to_path_buf(),
+            bytes: bytes[..dump.len].to_vec(),
+            fields,
+            revision: dump.format,
+            studio: studio.unwrap_or_default(),
+            id,
+            reel,
+        }
+    }
+}
+
+/// Values of one byte offset over all the headers of a kind
+struct OffsetStats {
+    offset: usize,
+    /// Fields the offset belongs to, more than one when revisions differ
+    field: String,
+    headers: usize,
+    /// Most common first
+    values: Vec<(u8, usize)>,
+    /// Goodman and Kruskal's lambda: how much knowing the revision, the
+    /// studio or the reel of a header tells its value, from 0 to 1
+    revision: f64,
+    studio: f64,
+    reel: f64,
+    outliers: Vec<(u8, PathBuf)>,
+}
+
+impl OffsetStats {
+    fn is_unknown(&self) -> bool {
+        self.field.split('/').any(|f| f == "Unknown")
+    }
+}
+
+pub fn analyze_headers(
+    paths: &[PathBuf],
+    csv: Option<PathBuf>,
+    unknown_only: bool,
+    verbose: bool,
+) -> Result<()> {
+    let mut union = UnionFileSystem::from_paths(paths, verbose)?;
+    let mut snd_samples = vec![];
+    let mut hdr_samples = vec![];
+    for e in union.read_dir("/")? {
+        if !e.file_type().is_ok_and(|t| t == file::FileType::File) {
+            continue;
+        }
+        let path = e.path()?;
+        let mut file = union.open_file(&path)?;
+        let Ok(file_type) = get_file_type(&mut file, &path, verbose) else {
+            continue;
+        };
+        let sample = match file_type {
+            FileType::Aud | FileType::Aue | FileType::Snd => read_snd_sample(&mut file, &path),
+            FileType::Hdr => read_hdr_sample(&mut file, &path),
+            _ => continue,
+        };
+        match (sample, file_type) {
+            (Ok(s), FileType::Hdr) => hdr_samples.push(s),
+            (Ok(s), _) => snd_samples.push(s),
+            (Err(e), _) => println!("Warning: skipping {}: {}", path.display(), e),
+        }
+    }
+    for h in &mut hdr_samples {
+        if let Some(s) = snd_samples
+            .iter()
+            .find(|s| s.id == h.id && s.reel == h.reel)
+        {
+            h.revision = s.revision.clone();
+        }
+    }
+    println!(
+        "Analyzed {} SND headers and {} HDR headers",
+        snd_samples.len(),
+        hdr_samples.len()
+    );
+
+    let mut rows = vec![];
+    for (kind, samples) in [("SND", &snd_samples), ("HDR", &hdr_samples)] {
+        let stats: Vec<OffsetStats> = get_stats(samples)
+            .into_iter()
+            .filter(|s| !unknown_only || s.is_unknown())
+            .collect();
+        print_stats(kind, &stats);
+        rows.extend(stats.into_iter().map(|s| (kind, s)));
+    }
+    match csv {
+        Some(p) => write_csv(&p, &rows, verbose),
+        None => Ok(()),
+    }
+}
+
+fn read_snd_sample(file: &mut dyn File, path: &Path) -> Result<Sample> {
+    let bytes = file.read_bytes(SND_HEADER_LEN_WITH_ENCRYPTION)?;
+    let array = bytes[..].try_into()?;
+    let data = decode_snd_header(file, array)?;
+    let dump = dump_snd(file, array, path)?;
+    Ok(Sample::new(
+        path,
+        dump,
+        &bytes,
+        data.studio,
+        data.id,
+        data.reel,
+    ))
+}
+
+fn read_hdr_sample(file: &mut dyn File, path: &Path) -> Result<Sample> {
+    let bytes = file.read_bytes(HDR_LEN as usize)?;
+    let data = decode_hdr(&bytes, path)?;
+    let dump = dump_hdr(&bytes, path)?;
+    let mut sample = Sample::new(path, dump, &bytes, Some(data.studio), data.id, data.reel);
+    sample.revision = "unknown".to_string();
+    Ok(sample)
+}
+
+fn get_stats(samples: &[Sample]) -> Vec<OffsetStats> {
+    let len = samples.iter().map(|s| s.bytes.len()).max().unwrap_or(0);
+    (0..len)
+        .map(|offset| {
+            // Only encrypted SND headers have bytes 92 to 94
+            let samples: Vec<&Sample> = samples.iter().filter(|s| s.bytes.len() > offset).collect();
+            get_offset_stats(&samples, offset)
+        })
+        .collect()
+}
+
+fn get_offset_stats(samples: &[&Sample], offset: usize) -> OffsetStats {
+    let mut counts: BTreeMap<u8, usize> = BTreeMap::new();
+    let mut fields: BTreeMap<&str, usize> = BTreeMap::new();
+    for s in samples {
+        *counts.entry(s.bytes[offset]).or_default() += 1;
+        *fields.entry(&s.fields[offset]).or_default() += 1;
+    }
+    let values: Vec<(u8, usize)> = counts
+        .into_iter()
+        .sorted_by_key(|(_, count)| std::cmp::Reverse(*count))
+        .collect();
+
+    let mut outliers = vec![];
+    if let Some((mode, count)) = values.first()
+        && values.len() > 1
+        && *count as f64 >= OUTLIER_MODE_SHARE * samples.len() as f64
+    {
+        outliers = samples
+            .iter()
+            .filter(|s| s.bytes[offset] != *mode)
+            .map(|s| (s.bytes[offset], s.path.clone()))
+            .collect();
+    }
+
+    OffsetStats {
+        offset,
+        field: fields
+            .into_iter()
+            .sorted_by_key(|(_, count)| std::cmp::Reverse(*count))
+            .map(|(f, _)| f)
+            .join("/"),
+        headers: samples.len(),
+        revision: get_lambda(samples, offset, |s| s.revision.clone()),
+        studio: get_lambda(samples, offset, |s| s.studio.clone()),
+        reel: get_lambda(samples, offset, |s| s.reel.to_string()),
+        values,
+        outliers,
+    }
+}
+
+/// Share of the errors made guessing the most common value of the offset that
+/// are avoided by guessing the most common value of the header's group
+/// instead. 0 when the offset has a single value.
+fn get_lambda<F: Fn(&Sample) -> String>(samples: &[&Sample], offset: usize, group: F) -> f64 {
+    let mut counts: HashMap<(String, u8), usize> = HashMap::new();
+    let mut totals: HashMap<u8, usize> = HashMap::new();
+    for s in samples {
+        *counts.entry((group(s), s.bytes[offset])).or_default() += 1;
+        *totals.entry(s.bytes[offset]).or_default() += 1;
+    }
+    let mut group_modes: HashMap<&str, usize> = HashMap::new();
+    for ((g, _), count) in &counts {
+        let mode = group_modes.entry(g).or_default();
+        *mode = (*mode).max(*count);
+    }
+    let mode = totals.values().max().copied().unwrap_or(0);
+    if mode == samples.len() {
+        return 0.0;
+    }
+    let group_modes: usize = group_modes.values().sum();
+    (group_modes - mode) as f64 / (samples.len() - mode) as f64
+}
+
+fn print_stats(kind: &str, stats: &[OffsetStats]) {
+    let (constant, varying): (Vec<&OffsetStats>, Vec<&OffsetStats>) =
+        stats.iter().partition(|s| s.values.len() <= 1);
+    if stats.is_empty() {
+        return;
+    }
+    println!();
+    println!(
+        "{} headers: {} offsets with a single value, {} with several:",
+        kind,
+        constant.len(),
+        varying.len()
+    );
+    for s in varying {
+        let values = s
+            .values
+            .iter()
+            .take(MAX_PRINTED_VALUES)
+            .map(|(v, count)| {
+                format!(
+                    "{:#04x} {:.0}%",
+                    v,
+                    *count as f64 * 100.0 / s.headers as f64
+                )
+            })
+            .join(", ");
+        let more = s.values.len().saturating_sub(MAX_PRINTED_VALUES);
+        println!(
+            "  {} {}: {}{}; lambda revision {:.2}, studio {:.2}, reel {:.2}",
+            s.offset,
+            s.field,
+            values,
+            if more > 0 {
+                format!(" and {} more", more)
+            } else {
+                String::new()
+            },
+            s.revision,
+            s.studio,
+            s.reel
+        );
+        for (v, path) in s.outliers.iter().take(MAX_PRINTED_OUTLIERS) {
+            println!("    Outlier {:#04x}: {}", v, path.display());
+        }
+        if s.outliers.len() > MAX_PRINTED_OUTLIERS {
+            println!(
+                "    and {} more outliers",
+                s.outliers.len() - MAX_PRINTED_OUTLIERS
+            );
+        }
+    }
+}
+
+fn write_csv(path: &Path, rows: &[(&str, OffsetStats)], verbose: bool) -> Result<()> {
+    let mut csv = String::from(
+        "kind,offset,field,headers,distinct_values,values,lambda_revision,lambda_studio,lambda_reel,outliers\n",
+    );
+    for (kind, s) in rows {
+        let values = s
+            .values
+            .iter()
+            .map(|(v, count)| format!("{:#04x}:{}", v, count))
+            .join(" ");
+        let outliers = s
+            .outliers
+            .iter()
+            .map(|(v, p)| format!("{:#04x} {}", v, p.display()))
+            .join("; ");
+        let fields = [
+            kind.to_string(),
+            s.offset.to_string(),
+            s.field.clone(),
+            s.headers.to_string(),
+            s.values.len().to_string(),
+            values,
+            format!("{:.3}", s.revision),
+            format!("{:.3}", s.studio),
+            format!("{:.3}", s.reel),
+            outliers,
+        ];
+        csv.push_str(&fields.iter().map(|f| escape_csv(f)).join(","));
+        csv.push('\n');
+    }
+    fs::write(path, csv)?;
+    if verbose {
+        println!(
+            "write_csv: {} rows written to {}",
+            rows.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_sample(name: &str, studio: &str, reel: u8, byte: u8) -> Sample {
+        Sample {
+            path: PathBuf::from(name),
+            bytes: vec![0, byte],
+            fields: vec!["Title".to_string(), "Unknown".to_string()],
+            revision: "SND (XD)".to_string(),
+            studio: studio.to_string(),
+            id: 1,
+            reel,
+        }
+    }
+
+    #[test]
+    fn test_offset_stats() {
+        let mut samples: Vec<Sample> = (0..10)
+            .map(|i| get_sample(&format!("{}.snd", i), "WB", i % 2 + 1, 0))
+            .collect();
+        samples.push(get_sample("odd.snd", "FOX", 1, 7));
+        let stats = get_stats(&samples);
+
+        assert_eq!(stats[0].values, vec![(0, 11)]);
+        assert_eq!(stats[0].revision, 0.0);
+        assert!(stats[0].outliers.is_empty());
+
+        let s = &stats[1];
+        assert!(s.is_unknown());
+        assert_eq!(s.values, vec![(0, 10), (7, 1)]);
+        assert_eq!(s.studio, 1.0);
+        assert_eq!(s.reel, 0.0);
+        assert_eq!(s.revision, 0.0);
+        assert_eq!(s.outliers, vec![(7, PathBuf::from("odd.snd"))]);
+    }
+
+    #[test]
+    fn test_escape_csv() {
+        assert_eq!(escape_csv("Title/Unknown"), "Title/Unknown");
+        assert_eq!(
+            escape_csv("0x01 a.snd; 0x02 b,c.snd"),
+            "\"0x01 a.snd; 0x02 b,c.snd\""
+        );
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
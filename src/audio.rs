@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    detect::{FileType, get_file_type},
+    file::{File, FileSystem},
+    httpfile::is_http_url,
+    info::open_input_file,
+    metadata::{OFFSET_FRAMES_PER_SECOND, Offset},
+    osfile::OsFileSystem,
+    snd::{
+        SND_HEADER_LEN_WITH_ENCRYPTION, SND_TRACK_BLOCK_LEN, get_snd_header_len,
+        is_encrypted_snd_header,
+    },
+    streamfile::is_stdin_path,
+    unionfile::UnionFileSystem,
+};
+
+/// Where the audio frames of a SND/AUD/AUE file are: after the header, each
+/// 1/30 s frame holds one block of SND_TRACK_BLOCK_LEN bytes per track.
+pub struct AudioLayout {
+    pub header_len: u64,
+    pub tracks: u8,
+    pub encrypted: bool,
+}
+
+impl AudioLayout {
+    pub fn from_file(file: &mut dyn File) -> Result<Self> {
+        let bytes = file.read_bytes(SND_HEADER_LEN_WITH_ENCRYPTION)?;
+        let header: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION] = bytes[..].try_into()?;
+        let tracks = header[82];
+        if tracks == 0 {
+            return Err(anyhow!("No audio track in header"));
+        }
+        Ok(AudioLayout {
+            header_len: get_snd_header_len(header) as u64,
+            tracks,
+            encrypted: is_encrypted_snd_header(header),
+        })
+    }
+
+    pub fn frame_len(&self) -> u64 {
+        self.tracks as u64 * SND_TRACK_BLOCK_LEN as u64
+    }
+
+    pub fn frame_offset(&self, index: u64) -> u64 {
+        self.header_len + index * self.frame_len()
+    }
+}
+
+pub struct AudioReport {
+    pub layout: AudioLayout,
+    /// Complete frames
+    pub frames: u64,
+    /// Bytes of an incomplete last frame
+    pub truncated_len: u64,
+}
+
+impl AudioReport {
+    pub fn duration(&self) -> Offset {
+        Offset::from_frames(self.frames)
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.truncated_len > 0
+    }
+}
+
+/// Counts the frames of a SND/AUD/AUE file. The frames have no known sync
+/// word or embedded timecode to check, only an incomplete last frame can be
+/// found.
+pub fn analyze_audio(file: &mut dyn File) -> Result<AudioReport> {
+    let layout = AudioLayout::from_file(file)?;
+    let payload_len = file.len()?.saturating_sub(layout.header_len);
+    Ok(AudioReport {
+        frames: payload_len / layout.frame_len(),
+        truncated_len: payload_len % layout.frame_len(),
+        layout,
+    })
+}
+
+pub fn print_audio_report(name: &str, report: &AudioReport) {
+    let duration = report.duration();
+    println!("{}:", name);
+    println!(
+        "  Frames: {} of {} tracks ({} bytes each){}",
+        report.frames,
+        report.layout.tracks,
+        report.layout.frame_len(),
+        if report.layout.encrypted {
+            ", encrypted"
+        } else {
+            ""
+        }
+    );
+    println!(
+        "  Duration: {} ({} s + {}/{} s)",
+        duration,
+        report.frames / OFFSET_FRAMES_PER_SECOND,
+        report.frames % OFFSET_FRAMES_PER_SECOND,
+        OFFSET_FRAMES_PER_SECOND
+    );
+    if report.is_damaged() {
+        println!(
+            "  Frame {}: truncated frame, only {} bytes",
+            report.frames, report.truncated_len
+        );
+    } else {
+        println!("  No damaged frame found");
+    }
+}
+
+/// Opens every SND/AUD/AUE file of the inputs: audio files are used directly,
+/// everything else (directories, disc and drive images) is read as a library.
+pub fn open_audio_inputs(paths: &[PathBuf], verbose: bool) -> Result<Vec<(String, Box<dyn File>)>> {
+    let mut os_fs = OsFileSystem;
+    let mut files: Vec<(String, Box<dyn File>)> = vec![];
+    let mut libraries = vec![];
+    for path in paths {
+        if !is_stdin_path(path) && !is_http_url(path) && os_fs.is_dir(path) {
+            libraries.push(path.clone());
+            continue;
+        }
+        let mut file = open_input_file(path)?;
+        match get_file_type(file.as_mut(), path, verbose)? {
+            FileType::Aud | FileType::Aue | FileType::Snd => {
+                files.push((path.display().to_string(), file))
+            }
+            _ => libraries.push(path.clone()),
+        }
+    }
+    if !libraries.is_empty() {
+        let mut union = UnionFileSystem::from_paths(&libraries, verbose)?;
+        for path in union.find_audio_files()? {
+            let file = union.open_file(&path)?;
+            files.push((union.get_display_path(&path), Box::new(file)));
+        }
+    }
+    Ok(files)
+}
+
+/// Checks the audio frames of every SND/AUD/AUE file of the inputs, fails if
+/// one of them is damaged.
+pub fn check_audio(paths: &[PathBuf], verbose: bool) -> Result<()> {
+    let files = open_audio_inputs(paths, verbose)?;
+    if files.is_empty() {
+        return Err(anyhow!("No SND/AUD/AUE file found"));
+    }
+    let mut damaged = 0;
+    for (name, mut file) in files {
+        match analyze_audio(file.as_mut()) {
+            Ok(report) => {
+                print_audio_report(&name, &report);
+                if report.is_damaged() {
+                    damaged += 1;
+                }
+            }
+            Err(e) => {
+                println!("{}: {}", name, e);
+                damaged += 1;
+            }
+        }
+    }
+    if damaged > 0 {
+        return Err(anyhow!("{} audio files are damaged", damaged));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memfile::MemoryFile,
+        snd::{get_test_header, get_test_snd},
+    };
+
+    fn get_reel(tracks: u8, frames: usize) -> Vec<u8> {
+        let mut metadata = get_test_header(1234, 1);
+        metadata.tracks = tracks;
+        get_test_snd(&metadata, frames)
+    }
+
+    #[test]
+    fn test_analyze_audio() {
+        let frame_len = 2 * SND_TRACK_BLOCK_LEN;
+        let mut file = MemoryFile::from(get_reel(2, 5));
+        let report = analyze_audio(&mut file).unwrap();
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.layout.frame_len(), frame_len as u64);
+        assert!(!report.is_damaged());
+        assert_eq!(report.duration().to_frames(), 5);
+
+        let mut reel = get_reel(2, 5);
+        reel.extend([0; 10]);
+        let mut file = MemoryFile::from(reel);
+        let report = analyze_audio(&mut file).unwrap();
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.truncated_len, 10);
+        assert!(report.is_damaged());
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::{Result, anyhow};
 
+/// Packed BCD: the tens digit in the high nibble, the units in the low one.
+/// Both functions are each other's inverse over 0 to 99 and the bytes packing
+/// them, everything else is an error.
 pub fn bcd_to_decimal(value: u8) -> Result<u8> {
     let tens = value >> 4;
     let units = value & 0xF;
     if tens >= 10 || units >= 10 {
-        Err(anyhow!("Could not unpack BCD value: {:#02x}", value))
+        Err(anyhow!("Could not unpack BCD value: {:#04x}", value))
     } else {
         Ok(tens * 10 + units)
     }
@@ -36,5 +39,22 @@ mod tests {
         assert_eq!(decimal_to_bcd(0).unwrap(), 0);
         assert_eq!(decimal_to_bcd(15).unwrap(), 0x15);
         assert_eq!(decimal_to_bcd(99).unwrap(), 0x99);
+        assert!(decimal_to_bcd(100).is_err());
+    }
+
+    #[test]
+    fn test_bcd_round_trip() {
+        for value in 0..=99 {
+            assert_eq!(
+                bcd_to_decimal(decimal_to_bcd(value).unwrap()).unwrap(),
+                value
+            );
+        }
+        for byte in 0..=u8::MAX {
+            match bcd_to_decimal(byte) {
+                Ok(value) => assert_eq!(decimal_to_bcd(value).unwrap(), byte),
+                Err(_) => assert!(byte >> 4 > 9 || byte & 0xF > 9),
+            }
+        }
     }
 }
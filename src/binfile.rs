@@ -0,0 +1,153 @@
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use anyhow::{Result, anyhow};
+
+use crate::file::File;
+
+/// Raw CD sectors, as written by most "BIN/CUE" rippers
+const BIN_SECTOR_LEN: u64 = 2352;
+const DATA_SECTOR_LEN: u64 = 2048;
+const SYNC_PATTERN: [u8; 12] = [
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
+];
+/// The primary volume descriptor of an ISO 9660 filesystem
+const ISO_DESCRIPTOR_SECTOR: u64 = 16;
+const ISO_MAGIC: [u8; 5] = [0x43, 0x44, 0x30, 0x30, 0x31];
+
+/// How likely (0 to 100) the file is a raw CD image holding an ISO 9660
+/// filesystem.
+pub fn get_bin_confidence(file: &mut dyn File) -> u8 {
+    match get_bin_data_offset(file) {
+        Some(offset) => {
+            let at = ISO_DESCRIPTOR_SECTOR * BIN_SECTOR_LEN + offset + 1;
+            match file.read_exact_bytes_at(ISO_MAGIC.len(), at) {
+                Ok(bytes) if bytes == ISO_MAGIC => 90,
+                _ => 40,
+            }
+        }
+        None => 0,
+    }
+}
+
+/// Offset of the user data in each sector: mode 1 sectors have a 16 bytes
+/// header, mode 2 (XA form 1) sectors an additional 8 bytes subheader.
+fn get_bin_data_offset(file: &mut dyn File) -> Option<u64> {
+    let header = file
+        .read_exact_bytes_at(16, ISO_DESCRIPTOR_SECTOR * BIN_SECTOR_LEN)
+        .ok()?;
+    if !header.starts_with(&SYNC_PATTERN) {
+        return None;
+    }
+    match header[15] {
+        1 => Some(16),
+        2 => Some(24),
+        _ => None,
+    }
+}
+
+/// The 2048 bytes user data of each sector of a raw CD image, which can then
+/// be read like an ISO file.
+pub struct BinFile {
+    file: Box<dyn File>,
+    data_offset: u64,
+    current: u64,
+}
+
+impl BinFile {
+    pub fn from_file(mut file: Box<dyn File>) -> Result<Self> {
+        let data_offset = get_bin_data_offset(file.as_mut())
+            .ok_or(anyhow!("Could not find raw CD sectors (mode 1 or 2)"))?;
+        Ok(BinFile {
+            file,
+            data_offset,
+            current: 0,
+        })
+    }
+
+    pub fn mode(&self) -> u8 {
+        if self.data_offset == 16 { 1 } else { 2 }
+    }
+}
+
+impl File for BinFile {
+    fn len(&mut self) -> Result<u64> {
+        Ok(self.file.len()? / BIN_SECTOR_LEN * DATA_SECTOR_LEN)
+    }
+}
+
+impl Read for BinFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self
+            .len()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if self.current >= len || buf.is_empty() {
+            return Ok(0);
+        }
+        let sector = self.current / DATA_SECTOR_LEN;
+        let offset = self.current % DATA_SECTOR_LEN;
+        let count = (buf.len() as u64)
+            .min(DATA_SECTOR_LEN - offset)
+            .min(len - self.current) as usize;
+        let at = sector * BIN_SECTOR_LEN + self.data_offset + offset;
+        let count = self.file.read_buffer_at(&mut buf[..count], at)?;
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for BinFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => {
+                let len = self
+                    .len()
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                len as i64 + from_end
+            }
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memfile::MemoryFile;
+
+    #[test]
+    fn test_read_mode1_sectors() {
+        let sectors = ISO_DESCRIPTOR_SECTOR + 2;
+        let mut data = vec![];
+        for s in 0..sectors {
+            data.extend_from_slice(&SYNC_PATTERN);
+            data.extend_from_slice(&[0, 2, 0, 1]);
+            let mut user = vec![s as u8; DATA_SECTOR_LEN as usize];
+            if s == ISO_DESCRIPTOR_SECTOR {
+                user[1..6].copy_from_slice(&ISO_MAGIC);
+            }
+            data.extend_from_slice(&user);
+            data.extend_from_slice(&[0xEE; 288]);
+        }
+        let mut raw = MemoryFile::from(data);
+        assert_eq!(get_bin_confidence(&mut raw), 90);
+
+        let mut file = BinFile::from_file(Box::new(raw)).unwrap();
+        assert_eq!(file.mode(), 1);
+        assert_eq!(file.len().unwrap(), sectors * DATA_SECTOR_LEN);
+        let bytes = file.read_exact_bytes_at(4, 0x8000 - 2).unwrap();
+        assert_eq!(bytes, [15, 15, 16, 0x43]);
+        let mut rest = vec![];
+        file.seek(SeekFrom::Start(0x8800)).unwrap();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![17; DATA_SECTOR_LEN as usize]);
+    }
+}
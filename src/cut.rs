@@ -0,0 +1,177 @@
+use std::{io::Write, path::Path};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    audio::AudioLayout,
+    file::{File, WritableFileSystem},
+    info::open_input_file,
+    metadata::Offset,
+    osfile::OsFileSystem,
+    snd::{decode_snd_header_from_file, set_header_offsets},
+};
+
+/// Frames copied at once
+const FRAMES_PER_COPY: u64 = 30;
+
+/// Copies the frames of a reel from `from` to `to` (excluded) into a new reel.
+pub fn cut_reel(
+    input: &Path,
+    output: &Path,
+    from: Offset,
+    to: Offset,
+    verbose: bool,
+) -> Result<()> {
+    let mut file = open_input_file(input)?;
+    let mut output_fs = OsFileSystem;
+    let mut output_file = output_fs.create_file(output)?;
+    let frames = cut_frames(file.as_mut(), input, &mut output_file, from, to, verbose)?;
+    output_file.flush()?;
+    println!(
+        "Created {:?} ({} to {}, {})",
+        output,
+        from,
+        to,
+        Offset::from_frames(frames)
+    );
+    Ok(())
+}
+
+/// The frames are copied as they are, the first one being at the start offset
+/// of the header. The header is kept but for the start and end offsets, set
+/// to the cut. Returns the number of frames copied.
+fn cut_frames(
+    file: &mut dyn File,
+    path: &Path,
+    output: &mut dyn Write,
+    from: Offset,
+    to: Offset,
+    verbose: bool,
+) -> Result<u64> {
+    let metadata = decode_snd_header_from_file(file, path)?;
+    let layout = AudioLayout::from_file(file)?;
+    let frames = file.len()?.saturating_sub(layout.header_len) / layout.frame_len();
+    if frames == 0 {
+        return Err(anyhow!("No complete audio frame in {}", path.display()));
+    }
+    let first_timecode = metadata.start_offset.map_or(0, |o| o.to_frames());
+    if from.to_frames() >= to.to_frames()
+        || from.to_frames() < first_timecode
+        || to.to_frames() > first_timecode + frames
+    {
+        return Err(anyhow!(
+            "Can not cut from {} to {}, the reel goes from {} to {}",
+            from,
+            to,
+            Offset::from_frames(first_timecode),
+            Offset::from_frames(first_timecode + frames)
+        ));
+    }
+    let first = from.to_frames() - first_timecode;
+    let last = to.to_frames() - first_timecode;
+    if verbose {
+        println!(
+            "cut_frames: frames {}..{} of {}, {} tracks",
+            first, last, frames, layout.tracks
+        );
+    }
+
+    let mut header = file.read_exact_bytes_at(layout.header_len as usize, 0)?;
+    set_header_offsets(&mut header, &Some(from), &Some(to))?;
+    output.write_all(&header)?;
+    for index in (first..last).step_by(FRAMES_PER_COPY as usize) {
+        let count = FRAMES_PER_COPY.min(last - index);
+        let bytes = file.read_exact_bytes_at(
+            (count * layout.frame_len()) as usize,
+            layout.frame_offset(index),
+        )?;
+        output.write_all(&bytes)?;
+    }
+    Ok(last - first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memfile::MemoryFile,
+        snd::{SND_HEADER_LEN, SND_TRACK_BLOCK_LEN, get_test_header, get_test_snd},
+    };
+
+    /// Reel starting at `start` whose frames are filled with their index
+    fn get_reel(start: Option<Offset>, frames: usize) -> (Vec<u8>, usize) {
+        let mut header = get_test_header(1234, 1);
+        header.start_offset = start;
+        let frame_len = header.tracks as usize * SND_TRACK_BLOCK_LEN;
+        (get_test_snd(&header, frames), frame_len)
+    }
+
+    #[test]
+    fn test_cut_frames() {
+        let (reel, frame_len) = get_reel(None, 6);
+
+        let mut cut = vec![];
+        let frames = cut_frames(
+            &mut MemoryFile::from(reel.clone()),
+            Path::new("r14t5.aud"),
+            &mut cut,
+            Offset::from_frames(1),
+            Offset::from_frames(4),
+            false,
+        )
+        .unwrap();
+        assert_eq!(frames, 3);
+        assert_eq!(
+            cut[SND_HEADER_LEN..],
+            reel[SND_HEADER_LEN + frame_len..SND_HEADER_LEN + 4 * frame_len]
+        );
+
+        let mut file = MemoryFile::from(cut);
+        let metadata = decode_snd_header_from_file(&mut file, Path::new("cut.aud")).unwrap();
+        assert_eq!(metadata.start_offset, Some(Offset::from_frames(1)));
+        assert_eq!(metadata.end_offset, Some(Offset::from_frames(4)));
+
+        // Out of the reel
+        let result = cut_frames(
+            &mut MemoryFile::from(reel),
+            Path::new("r14t5.aud"),
+            &mut vec![],
+            Offset::from_frames(2),
+            Offset::from_frames(7),
+            false,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cut_frames_from_start_offset() {
+        let (reel, frame_len) = get_reel(Some(Offset::from_frames(100)), 10);
+
+        let mut cut = vec![];
+        let frames = cut_frames(
+            &mut MemoryFile::from(reel.clone()),
+            Path::new("r1t5.aud"),
+            &mut cut,
+            Offset::from_frames(103),
+            Offset::from_frames(106),
+            false,
+        )
+        .unwrap();
+        assert_eq!(frames, 3);
+        let audio = &cut[SND_HEADER_LEN..];
+        assert_eq!(audio.len(), 3 * frame_len);
+        assert!(audio[..frame_len].iter().all(|b| *b == 3));
+        assert!(audio[2 * frame_len..].iter().all(|b| *b == 5));
+
+        // Before the start offset
+        let result = cut_frames(
+            &mut MemoryFile::from(reel),
+            Path::new("r1t5.aud"),
+            &mut vec![],
+            Offset::from_frames(99),
+            Offset::from_frames(101),
+            false,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -4,12 +4,10 @@ use anyhow::{Result, anyhow};
 
 use crate::{
     cd::{CdTreeEntries, get_if_dts_cd_dir_entry},
-    ext234::is_ext234_image_file,
     file::{DirEntry, File, FileSystem},
-    hdd::is_hdd_img_file,
-    hdr::is_hdr_file,
-    iso::is_iso_file,
-    squash::is_squashfs_file,
+    probe::{
+        CERTAIN_CONFIDENCE, EXTENSION_CONFIDENCE, FILE_PROBES, FileProbe, MIN_CONTENT_CONFIDENCE,
+    },
 };
 
 pub enum DirType {
@@ -17,9 +15,10 @@ pub enum DirType {
     DiscTree(CdTreeEntries),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileType {
     Iso,
+    Bin,
     Aud,
     Aue,
     Hdr,
@@ -27,14 +26,7 @@ pub enum FileType {
     SquashFs,
     HddImg,
     PartitionImg,
-}
-
-#[derive(Debug)]
-pub enum SimpleFileType {
-    Aud,
-    Aue,
-    Hdr,
-    Snd,
+    VirtualDisk,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -69,138 +61,131 @@ impl fmt::Display for SndTrackType {
     }
 }
 
+/// A file type one of the probes found, scored from the content and the
+/// extension.
+pub struct FileTypeCandidate {
+    pub probe: &'static FileProbe,
+    pub file_type: FileType,
+    pub content_confidence: u8,
+    pub extension_match: bool,
+}
+
+impl FileTypeCandidate {
+    pub fn confidence(&self) -> u8 {
+        let extension = if self.extension_match {
+            EXTENSION_CONFIDENCE
+        } else {
+            0
+        };
+        (self.content_confidence + extension).min(100)
+    }
+
+    /// Either the content is convincing enough alone, or the extension
+    /// matches (files with a broken header are then still decoded, and the
+    /// error says what is wrong with them).
+    pub fn is_acceptable(&self) -> bool {
+        self.content_confidence >= MIN_CONTENT_CONFIDENCE || self.extension_match
+    }
+}
+
 pub fn get_file_type<P: AsRef<Path>>(
     file: &mut dyn File,
     path: P,
     verbose: bool,
 ) -> Result<FileType> {
-    get_simple_file_type_from_extension(&path, verbose)
-        .and_then(|t| Some(simple_file_type_to_file_type(t)))
-        .or(get_remaining_file_type_from_extension(&path, verbose))
-        .or(try_get_simple_file_type_from_content(file, verbose)
-            .and_then(|t| Some(simple_file_type_to_file_type(t))))
-        .or(try_get_remaining_file_type_from_content(file, verbose))
+    get_file_type_candidates(file, &path, false, verbose)
+        .into_iter()
+        .find(|c| c.is_acceptable())
+        .map(|c| c.file_type)
         .ok_or(anyhow!(
             "Could not recognize file type for {}",
             path.as_ref().display()
         ))
 }
 
-pub fn get_dir_type<FS: FileSystem<File = F, DirEntry = D>, D: DirEntry, F: File + 'static>(
-    fs: &mut FS,
-    entries: &Vec<D>,
-    verbose: bool,
-) -> Result<DirType> {
-    match get_if_dts_cd_dir_entry(fs, entries, verbose)? {
-        Some(cd) => Ok(DirType::DiscTree(cd)),
-        None => Ok(DirType::Regular),
-    }
-}
-
-fn get_simple_file_type_from_extension<P: AsRef<Path>>(
-    path: P,
-    verbose: bool,
-) -> Option<SimpleFileType> {
-    let t = match path
-        .as_ref()
-        .extension()?
-        .to_string_lossy()
-        .to_ascii_lowercase()
-        .as_str()
-    {
-        "aud" => Some(SimpleFileType::Aud),
-        "aue" => Some(SimpleFileType::Aue),
-        "hdr" => Some(SimpleFileType::Hdr),
-        "snd" => Some(SimpleFileType::Snd),
-        _ => None,
-    };
-
-    if verbose {
-        println!("get_simple_file_type_from_extension: {:?}", t);
-    }
-
-    return t;
-}
-
-fn get_remaining_file_type_from_extension<P: AsRef<Path>>(
+/// Runs the probes of FILE_PROBES, best candidates first. Unless `all` is
+/// set, probing stops at the first certain match.
+pub fn get_file_type_candidates<P: AsRef<Path>>(
+    file: &mut dyn File,
     path: P,
+    all: bool,
     verbose: bool,
-) -> Option<FileType> {
-    let t = match path
+) -> Vec<FileTypeCandidate> {
+    let extension = path
         .as_ref()
-        .extension()?
-        .to_string_lossy()
-        .to_ascii_lowercase()
-        .as_str()
-    {
-        "iso" => Some(FileType::Iso),
-        _ => None,
-    };
-    if verbose {
-        println!("get_remaining_file_type_from_extension, found: {:?}", t);
-    }
-    return t;
-}
-
-fn try_get_simple_file_type_from_content(
-    file: &mut dyn File,
-    verbose: bool,
-) -> Option<SimpleFileType> {
-    if is_hdr_file(file) {
-        if verbose {
-            println!("try_get_simple_file_type_from_content: Header");
-        }
-        return Some(SimpleFileType::Hdr);
-    }
-
-    // TODO AUD/AUE/SND
-    if verbose {
-        println!("try_get_simple_file_type_from_content: None");
-    }
-
-    return None;
-}
-
-fn try_get_remaining_file_type_from_content(
-    file: &mut dyn File,
-    verbose: bool,
-) -> Option<FileType> {
-    if is_iso_file(file) {
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let mut candidates = vec![];
+    for probe in &FILE_PROBES {
+        let (file_type, content_confidence) = (probe.probe)(file, &extension);
+        let extension_match = probe.extensions.contains(&extension.as_str());
         if verbose {
-            println!("try_get_remaining_file_type_from_content: Iso");
+            println!(
+                "get_file_type_candidates: {}: content {}%{}",
+                probe.name,
+                content_confidence,
+                if extension_match {
+                    ", extension matches"
+                } else {
+                    ""
+                }
+            );
         }
-        return Some(FileType::Iso);
-    }
-
-    if is_squashfs_file(file) {
-        if verbose {
-            println!("try_get_remaining_file_type_from_content: SquashFS");
+        if content_confidence > 0 || extension_match {
+            candidates.push(FileTypeCandidate {
+                probe,
+                file_type,
+                content_confidence,
+                extension_match,
+            });
         }
-        return Some(FileType::SquashFs);
-    }
-
-    if is_hdd_img_file(file) {
-        if verbose {
-            println!("try_get_remaining_file_type_from_content: HDD Image");
+        if !all && content_confidence >= CERTAIN_CONFIDENCE {
+            break;
         }
-        return Some(FileType::HddImg);
     }
+    // Stable, probes run first win ties
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.confidence()));
+    candidates
+}
 
-    if is_ext234_image_file(file) {
-        if verbose {
-            println!("try_get_remaining_file_type_from_content: Ext2/3/4 Filesystem");
-        }
-        return Some(FileType::PartitionImg);
+pub fn get_dir_type<FS: FileSystem<File = F, DirEntry = D>, D: DirEntry, F: File + 'static>(
+    fs: &mut FS,
+    entries: &Vec<D>,
+    verbose: bool,
+) -> Result<DirType> {
+    match get_if_dts_cd_dir_entry(fs, entries, verbose)? {
+        Some(cd) => Ok(DirType::DiscTree(cd)),
+        None => Ok(DirType::Regular),
     }
-
-    return None;
 }
 
-fn simple_file_type_to_file_type(t: SimpleFileType) -> FileType {
-    match t {
-        SimpleFileType::Aud => FileType::Aud,
-        SimpleFileType::Aue => FileType::Aue,
-        SimpleFileType::Hdr => FileType::Hdr,
-        SimpleFileType::Snd => FileType::Snd,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memfile::MemoryFile;
+
+    #[test]
+    fn test_content_wins_over_extension() {
+        let mut hdr = vec![0; 0xCA];
+        hdr[..9].copy_from_slice(&[0xCA, 0x00, 0x01, 0x00, 0x04, 0x00, 0x44, 0x54, 0x53]);
+        let mut file = MemoryFile::from(hdr);
+        let candidates = get_file_type_candidates(&mut file, "reel.snd", true, false);
+        assert_eq!(candidates[0].file_type, FileType::Hdr);
+        assert_eq!(candidates[0].confidence(), 100);
+        assert!(candidates[1].extension_match);
+        assert_eq!(candidates[1].file_type, FileType::Snd);
+        assert_eq!(
+            get_file_type(&mut file, "reel.snd", false).unwrap(),
+            FileType::Hdr
+        );
+
+        // Unrecognized content is still decoded as what the extension says
+        let mut file = MemoryFile::from(vec![0; 0x100]);
+        assert_eq!(
+            get_file_type(&mut file, "reel.aud", false).unwrap(),
+            FileType::Aud
+        );
+        assert!(get_file_type(&mut file, "reel", false).is_err());
     }
 }
@@ -0,0 +1,392 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::{
+    detect::{FileType, get_file_type},
+    file::File,
+    hdr::{HDR_LEN, decode_hdr},
+    info::open_input_file,
+    json::write_json,
+    metadata::Offset,
+    snd::{SND_HEADER_LEN_WITH_ENCRYPTION, decode_snd_header, get_snd_header_len},
+};
+
+/// Bytes per row of the hex view
+const DUMP_ROW_LEN: usize = 16;
+
+#[derive(Serialize)]
+struct DumpJson {
+    data: Vec<HeaderDump>,
+}
+
+#[derive(Serialize)]
+pub struct HeaderDump {
+    pub path: String,
+    /// Like "SND (XD)", "HDR" or "TRLR"
+    pub format: String,
+    pub len: usize,
+    pub fields: Vec<FieldDump>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct FieldDump {
+    pub name: String,
+    pub start: usize,
+    /// Exclusive
+    pub end: usize,
+    /// Hex, space separated
+    pub bytes: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Bytes no field is known for
+    pub unknown: bool,
+    #[serde(skip)]
+    raw: Vec<u8>,
+}
+
+/// Fields of a header, the bytes between them are added as unknown regions
+struct Layout<'a> {
+    bytes: &'a [u8],
+    fields: Vec<FieldDump>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Layout {
+            bytes,
+            fields: vec![],
+        }
+    }
+
+    fn field<T: ToString>(&mut self, name: &str, range: Range<usize>, value: Option<T>) {
+        self.push(name, range, value.map(|v| v.to_string()), false);
+    }
+
+    fn push(&mut self, name: &str, range: Range<usize>, value: Option<String>, unknown: bool) {
+        self.fields.push(FieldDump {
+            name: name.to_string(),
+            start: range.start,
+            end: range.end,
+            bytes: get_hex(&self.bytes[range.clone()]),
+            value,
+            unknown,
+            raw: self.bytes[range].to_vec(),
+        });
+    }
+
+    /// `gap` names the bytes between fields, unknown unless given
+    fn finish(mut self, gap: Option<&str>) -> Vec<FieldDump> {
+        self.fields.sort_by_key(|f| f.start);
+        let mut gaps = vec![];
+        let mut position = 0;
+        for f in &self.fields {
+            if f.start > position {
+                gaps.push(position..f.start);
+            }
+            position = position.max(f.end);
+        }
+        if position < self.bytes.len() {
+            gaps.push(position..self.bytes.len());
+        }
+        for range in gaps {
+            self.push(gap.unwrap_or("Unknown"), range, None, gap.is_none());
+        }
+        self.fields.sort_by_key(|f| f.start);
+        self.fields
+    }
+}
+
+pub fn dump_headers(paths: &[PathBuf], output_json: Option<PathBuf>, verbose: bool) -> Result<()> {
+    let mut dumps = vec![];
+    for path in paths {
+        let dump = dump_file(open_input_file(path)?.as_mut(), path, verbose)?;
+        print_dump(&dump);
+        println!();
+        dumps.push(dump);
+    }
+    match output_json {
+        Some(p) => write_json(p, &DumpJson { data: dumps }, verbose),
+        None => Ok(()),
+    }
+}
+
+fn dump_file(file: &mut dyn File, path: &Path, verbose: bool) -> Result<HeaderDump> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if extension == "txt" {
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        return Ok(dump_trailers(&bytes, path));
+    }
+    match get_file_type(file, path, verbose)? {
+        FileType::Aud | FileType::Aue | FileType::Snd => {
+            let bytes = file.read_bytes(SND_HEADER_LEN_WITH_ENCRYPTION)?;
+            dump_snd(file, bytes[..].try_into()?, path)
+        }
+        FileType::Hdr => dump_hdr(&file.read_bytes(HDR_LEN as usize)?, path),
+        t => Err(anyhow!(
+            "{} is not a SND, HDR or TRLR file but {:?}",
+            path.display(),
+            t
+        )),
+    }
+}
+
+pub fn dump_snd(
+    file: &mut dyn File,
+    bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION],
+    path: &Path,
+) -> Result<HeaderDump> {
+    let data = decode_snd_header(file, bytes)?;
+    let len = get_snd_header_len(bytes);
+    let mut layout = Layout::new(&bytes[..len]);
+    let language = data.xd.as_ref().and_then(|xd| xd.language.as_ref());
+    match data.xd.as_ref().map(|xd| &xd.xda) {
+        None => layout.field("Title", 0..67, Some(&data.title)),
+        Some(Some(xda)) => {
+            layout.field("Title", 0..18, Some(&data.title));
+            layout.field("Source", 18..31, xda.source.as_ref());
+            layout.field("Mix", 31..47, xda.mix.as_ref());
+            layout.field("LFE level", 47..50, xda.lfe_level.as_ref());
+            layout.field("XDA marker", 50..51, Some("D"));
+            layout.field("Surround delay", 51..55, xda.surround_delay.as_ref());
+            layout.field("Filters", 55..59, xda.filters.as_ref());
+            layout.field("Language", 60..65, language);
+        }
+        Some(None) => {
+            layout.field("Title", 0..60, Some(&data.title));
+            layout.field("Language", 60..65, language);
+        }
+    }
+    layout.field("Studio", 68..72, data.studio.as_ref());
+    layout.field("Optical backup", 75..76, Some(data.optical_backup));
+    layout.field("Reel", 78..79, Some(data.reel));
+    layout.field("Id", 80..82, Some(data.id));
+    layout.field("Tracks", 82..83, Some(data.tracks));
+    layout.field("Start offset", 84..88, data.start_offset.map(format_offset));
+    layout.field("End offset", 88..92, data.end_offset.map(format_offset));
+    if let Some(key) = data.encryption_key {
+        layout.field("Encrypted", 92..93, Some(true));
+        layout.field("Encryption key", 93..95, Some(format!("{:#06x}", key)));
+    }
+    Ok(HeaderDump {
+        path: path.display().to_string(),
+        format: format!("SND ({})", data.revision),
+        len,
+        fields: layout.finish(None),
+        warnings: data.warnings,
+    })
+}
+
+/// Offsets are whole frames, shown as timecode too
+fn format_offset(offset: Offset) -> String {
+    format!("{} ({})", offset, offset.to_timecode())
+}
+
+pub fn dump_hdr(bytes: &[u8], path: &Path) -> Result<HeaderDump> {
+    let data = decode_hdr(bytes, path)?;
+    let mut layout = Layout::new(bytes);
+    layout.field("Length", 0..1, Some(bytes[0]));
+    layout.field("Magic", 1..9, Some(String::from_utf8_lossy(&bytes[6..9])));
+    layout.field("Title", 9..27, Some(&data.title));
+    layout.field(
+        "Studio",
+        69..79,
+        Some(&data.studio).filter(|s| !s.is_empty()),
+    );
+    layout.field("Id", 79..81, Some(data.id));
+    layout.field("Reel", 91..92, Some(data.reel));
+    Ok(HeaderDump {
+        path: path.display().to_string(),
+        format: "HDR".to_string(),
+        len: bytes.len(),
+        fields: layout.finish(None),
+        warnings: vec![],
+    })
+}
+
+/// R14TRLR.TXT is text: each column of each line is a field, separators are
+/// not unknown.
+fn dump_trailers(bytes: &[u8], path: &Path) -> HeaderDump {
+    const COLUMNS: [&str; 5] = ["Title", "Id", "Start", "End", "Offset"];
+    let mut layout = Layout::new(bytes);
+    let mut warnings = vec![];
+    let mut start = 0;
+    for (i, line) in bytes.split_inclusive(|b| *b == b'\n').enumerate() {
+        let content_len = line.len()
+            - line
+                .iter()
+                .rev()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+        if line.starts_with(b";") {
+            let text = String::from_utf8_lossy(&line[..content_len]);
+            layout.field(
+                &format!("Comment {}", i + 1),
+                start..start + content_len,
+                Some(text),
+            );
+        } else {
+            let tokens = get_tokens(&line[..content_len]);
+            if tokens.len() == COLUMNS.len() {
+                for (name, range) in COLUMNS.iter().zip(tokens) {
+                    let value = String::from_utf8_lossy(&line[range.clone()]).to_string();
+                    layout.field(
+                        &format!("{} {}", name, i + 1),
+                        start + range.start..start + range.end,
+                        Some(value),
+                    );
+                }
+            } else if !tokens.is_empty() {
+                warnings.push(format!(
+                    "Line {}: {} columns instead of {}",
+                    i + 1,
+                    tokens.len(),
+                    COLUMNS.len()
+                ));
+            }
+        }
+        start += line.len();
+    }
+    HeaderDump {
+        path: path.display().to_string(),
+        format: "TRLR".to_string(),
+        len: bytes.len(),
+        fields: layout.finish(Some("Separator")),
+        warnings,
+    }
+}
+
+/// Byte ranges of the words of a line
+fn get_tokens(line: &[u8]) -> Vec<Range<usize>> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, b) in line.iter().enumerate() {
+        match (b.is_ascii_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push(s..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(s..line.len());
+    }
+    tokens
+}
+
+fn get_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn print_dump(dump: &HeaderDump) {
+    println!("{}: {} header, {} bytes", dump.path, dump.format, dump.len);
+    for w in &dump.warnings {
+        println!("  Warning: {}", w);
+    }
+    for f in &dump.fields {
+        let range = format!("{}..{}", f.start, f.end);
+        match &f.value {
+            Some(v) => println!("  {:<9} {}: {}", range, f.name, v),
+            None => println!("  {:<9} {}", range, f.name),
+        }
+        for row in f.raw.chunks(DUMP_ROW_LEN) {
+            let text: String = row
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            println!("            {:<47}  |{}|", get_hex(row), text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memfile::MemoryFile, metadata::SndFileMetadata, snd::encode_header};
+
+    fn check_contiguous(fields: &[FieldDump], len: usize) {
+        let mut position = 0;
+        for f in fields {
+            assert_eq!(f.start, position, "{}", f.name);
+            position = f.end;
+        }
+        assert_eq!(position, len);
+    }
+
+    #[test]
+    fn test_dump_snd() {
+        let metadata: SndFileMetadata = crate::snd::get_generic_trailers_header();
+        let mut bytes = encode_header(&metadata).unwrap();
+        bytes[66] = 0x12;
+        bytes.extend_from_slice(&[0; 3]);
+        let mut file = MemoryFile::from(bytes.clone());
+        let dump = dump_snd(
+            &mut file,
+            bytes[..].try_into().unwrap(),
+            Path::new("r14t5.aud"),
+        )
+        .unwrap();
+        assert_eq!(dump.format, "SND (XD)");
+        check_contiguous(&dump.fields, 92);
+        let unknown: Vec<_> = dump
+            .fields
+            .iter()
+            .filter(|f| f.unknown)
+            .map(|f| f.start..f.end)
+            .collect();
+        assert_eq!(unknown, vec![65..68, 72..75, 76..78, 79..80, 83..84]);
+        let title = &dump.fields[0];
+        assert_eq!(title.value.as_deref(), Some("Trailers Reel 14"));
+        assert!(title.bytes.starts_with("54 72 61"));
+        assert_eq!(dump.fields[2].bytes, "00 12 00");
+    }
+
+    #[test]
+    fn test_dump_trailers() {
+        let txt = b";NAME\tSERIAL\r\nTRAILER\t102\t0\t3\t92\r\n";
+        let dump = dump_trailers(txt, Path::new("r14trlr.txt"));
+        check_contiguous(&dump.fields, txt.len());
+        let names: Vec<_> = dump.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Comment 1",
+                "Separator",
+                "Title 2",
+                "Separator",
+                "Id 2",
+                "Separator",
+                "Start 2",
+                "Separator",
+                "End 2",
+                "Separator",
+                "Offset 2",
+                "Separator"
+            ]
+        );
+        assert_eq!(dump.fields[4].value.as_deref(), Some("102"));
+        assert!(dump.fields.iter().all(|f| !f.unknown));
+    }
+}
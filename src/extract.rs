@@ -1,5 +1,4 @@
 use std::{
-    fs::{self, create_dir_all},
     io::{Read, Write},
     path::{Path, PathBuf},
 };
@@ -8,12 +7,14 @@ use anyhow::Result;
 
 use crate::{
     detect::{DirType, get_dir_type},
-    file::{DirEntry, File, FileSystem},
+    file::{DirEntry, File, FileSystem, WritableFileSystem},
     hdr::decode_hdr_from_file,
+    info::print_union_conflicts,
     metadata::{HdrFileMetadata, SndFileMetadata, TrailersMetadata, TrailersMetadataTxtEntry},
     osfile::OsFileSystem,
     snd::{decode_snd_header_from_file, encode_header, get_generic_trailers_header},
-    trailers::encode_trailers_to_txt_file,
+    trailers::{TRAILER_UNIT_LEN, encode_trailers_to_txt_file},
+    unionfile::UnionFileSystem,
 };
 
 pub enum Feature {
@@ -69,18 +70,52 @@ struct SndEntryWithMetadata {
 
 struct Files {
     entries: Vec<EntryWithMetadata>,
+    /// The entries come from several merged copies of the same disc, so a
+    /// reel can be found more than once
+    union: bool,
 }
 
 pub fn extract_files(
     input: PathBuf,
+    union_with: Vec<PathBuf>,
     output: PathBuf,
     feature: Option<Feature>,
     trailers: Option<Trailers>,
     verbose: bool,
 ) -> Result<()> {
     let mut os_fs = OsFileSystem;
+    let mut output_fs = OsFileSystem;
+    if !union_with.is_empty() {
+        let mut paths = vec![input];
+        paths.extend(union_with);
+        let mut union = UnionFileSystem::from_paths(&paths, verbose)?;
+        print_union_conflicts(&mut union)?;
+        // the sources of a union are already library directories
+        let entries = union.read_dir("/")?;
+        let warnings = extract_from_regular_dir(
+            &mut union,
+            &mut output_fs,
+            &output,
+            &entries,
+            &feature,
+            &trailers,
+            true,
+        )?;
+        print_warnings(&warnings);
+        return Ok(());
+    }
     if os_fs.is_dir(&input) {
-        return extract_from_dir(&mut os_fs, &input, &output, &feature, &trailers, verbose);
+        let warnings = extract_from_dir(
+            &mut os_fs,
+            &mut output_fs,
+            &input,
+            &output,
+            &feature,
+            &trailers,
+            verbose,
+        )?;
+        print_warnings(&warnings);
+        return Ok(());
     } else if os_fs.is_file(&input) {
         // TODO check file type
         //return print_file_info(Box::new(os_fs.open_file(path)?), path);
@@ -89,77 +124,110 @@ pub fn extract_files(
     Ok(())
 }
 
-fn extract_from_dir<FS: FileSystem, P: AsRef<Path>>(
+fn extract_from_dir<FS: FileSystem, W: WritableFileSystem, P: AsRef<Path>>(
     fs: &mut FS,
+    output_fs: &mut W,
     input: &P,
     output: &P,
     feature: &Option<Feature>,
     trailers: &Option<Trailers>,
     verbose: bool,
-) -> Result<()>
+) -> Result<Vec<String>>
 where
     <FS as FileSystem>::File: 'static,
 {
     let entries = fs.read_dir(input)?;
     match get_dir_type(fs, &entries, verbose)? {
         DirType::DiscTree(_disc) => todo!(), // print_disc_dir_info(disc),
-        DirType::Regular => extract_from_regular_dir(fs, output, &entries, feature, trailers),
+        DirType::Regular => {
+            extract_from_regular_dir(fs, output_fs, output, &entries, feature, trailers, false)
+        }
+    }
+}
+
+/// Decodes a SND file and the HDR file next to it, adding the warnings of
+/// its header to `warnings`
+fn get_entry_with_metadata<FS: FileSystem, D: DirEntry>(
+    fs: &mut FS,
+    entries: &Vec<D>,
+    p: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<EntryWithMetadata>
+where
+    <FS as FileSystem>::File: 'static,
+{
+    let mut snd_file: Box<dyn File> = Box::new(fs.open_file(p)?);
+    let snd_metadata = decode_snd_header_from_file(snd_file.as_mut(), p)?;
+    for w in &snd_metadata.warnings {
+        warnings.push(format!("{}: {}", p.display(), w));
     }
+    let snd = SndEntryWithMetadata {
+        //file: snd_file,
+        path: p.to_path_buf(),
+        metadata: snd_metadata,
+    };
+    let hdr = match get_hdr_from_snd(entries, p) {
+        Some(f) => {
+            let mut hdr_file: Box<dyn File> = Box::new(fs.open_file(&f)?);
+            let hdr_metadata = decode_hdr_from_file(hdr_file.as_mut(), &f)?;
+            Some(HdrEntryWithMetadata {
+                //file: hdr_file,
+                path: f,
+                metadata: hdr_metadata,
+            })
+        }
+        None => None,
+    };
+    Ok(EntryWithMetadata { hdr, snd })
 }
 
-fn extract_from_regular_dir<FS: FileSystem, D: DirEntry, P: AsRef<Path>>(
+fn extract_from_regular_dir<FS: FileSystem, W: WritableFileSystem, D: DirEntry, P: AsRef<Path>>(
     fs: &mut FS,
+    output_fs: &mut W,
     output: &P,
     entries: &Vec<D>,
     feature: &Option<Feature>,
     trailers: &Option<Trailers>,
-) -> Result<()>
+    union: bool,
+) -> Result<Vec<String>>
 where
     <FS as FileSystem>::File: 'static,
 {
+    let mut warnings = vec![];
     let entries: Vec<EntryWithMetadata> = entries
         .iter()
-        .map(|e| {
+        .filter_map(|e| {
             let path = e.path().ok()?;
             let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
-            if extension == "snd" { Some(path) } else { None }
+            // merged copies of a disc can also hold reels that were already
+            // extracted
+            match extension.as_str() {
+                "snd" => Some(path),
+                "aud" | "aue" if union => Some(path),
+                _ => None,
+            }
         })
-        .flatten()
-        .map(|p| {
-            let mut snd_file: Box<dyn File> = Box::new(fs.open_file(&p).ok()?);
-            let snd_metadata = decode_snd_header_from_file(snd_file.as_mut(), &p).ok()?;
-            let snd = SndEntryWithMetadata {
-                //file: snd_file,
-                path: p.clone(),
-                metadata: snd_metadata,
-            };
-            let hdr = match get_hdr_from_snd(entries, &p.as_path()) {
-                Some(f) => {
-                    let mut hdr_file: Box<dyn File> = Box::new(fs.open_file(&f).ok()?);
-                    let hdr_metadata = decode_hdr_from_file(hdr_file.as_mut(), &f).ok()?;
-                    Some(HdrEntryWithMetadata {
-                        //file: hdr_file,
-                        path: f,
-                        metadata: hdr_metadata,
-                    })
+        .filter_map(
+            |p| match get_entry_with_metadata(fs, entries, &p, &mut warnings) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    warnings.push(format!("skipping {}: {}", p.display(), e));
+                    None
                 }
-                None => None,
-            };
-            Some(EntryWithMetadata { hdr, snd })
-        })
-        .flatten()
+            },
+        )
         .collect();
 
-    let mut files = Files { entries };
+    let mut files = Files { entries, union };
 
     match feature {
         Some(Feature::Name(f)) => {
             let entries = files.find_entries_by_title(&f.name);
-            files.convert_to_feature_files(entries, output)?;
+            files.convert_to_feature_files(fs, output_fs, entries, output)?;
         }
         Some(Feature::Id(f)) => {
             let entries = files.find_entries_by_id(f.id);
-            files.convert_to_feature_files(entries, output)?;
+            files.convert_to_feature_files(fs, output_fs, entries, output)?;
         }
         None => (),
     };
@@ -187,7 +255,7 @@ where
                     }
                 }
             }
-            files.convert_to_trailer_file(entries, output)?;
+            files.convert_to_trailer_file(fs, output_fs, entries, output)?;
         }
         Some(Trailers::Ids(t)) => {
             let mut entries: Vec<usize> = vec![];
@@ -211,14 +279,20 @@ where
                     }
                 }
             }
-            files.convert_to_trailer_file(entries, output)?;
+            files.convert_to_trailer_file(fs, output_fs, entries, output)?;
         }
         None => (),
     };
 
     println!("Done");
 
-    Ok(())
+    Ok(warnings)
+}
+
+fn print_warnings(warnings: &[String]) {
+    for w in warnings {
+        println!("Warning: {}", w);
+    }
 }
 
 impl Files {
@@ -244,8 +318,10 @@ impl Files {
         found
     }
 
-    fn convert_to_trailer_file<P: AsRef<Path>>(
+    fn convert_to_trailer_file<FS: FileSystem, W: WritableFileSystem, P: AsRef<Path>>(
         &mut self,
+        fs: &mut FS,
+        output_fs: &mut W,
         entries: Vec<usize>,
         output: P,
     ) -> Result<()> {
@@ -254,12 +330,12 @@ impl Files {
             //return Ok(())
         }
 
-        create_dir_all(output.as_ref())?;
+        output_fs.create_dir_all(output.as_ref())?;
 
         let snd_path = output.as_ref().join("r14t5.aud");
         let txt_path = output.as_ref().join("r14trlr.txt");
-        let mut snd_file = fs::File::create(&snd_path)?;
-        let mut txt_file = fs::File::create(&txt_path)?;
+        let mut snd_file = output_fs.create_file(&snd_path)?;
+        let mut txt_file = output_fs.create_file(&txt_path)?;
 
         let mut trailers_metadata = TrailersMetadata { entries: vec![] };
         let mut offset: usize = 92;
@@ -270,7 +346,7 @@ impl Files {
             let e = &mut self.entries[i];
             let mut data = vec![];
             //e.snd.file.read_to_end(&mut data)?;
-            let mut file = fs::File::open(&e.snd.path)?;
+            let mut file = fs.open_file(&e.snd.path)?;
             file.read_to_end(&mut data)?;
 
             let md5 = md5::compute(&data);
@@ -284,7 +360,7 @@ impl Files {
 
             snd_file.write_all(data)?;
 
-            let end = len / 3675;
+            let end = len / TRAILER_UNIT_LEN;
 
             let metadata = TrailersMetadataTxtEntry {
                 title: e.snd.metadata.title.clone(),
@@ -297,17 +373,22 @@ impl Files {
             offset += len;
         }
 
+        snd_file.flush()?;
+
         println!("Created {:?}", &snd_path);
 
         encode_trailers_to_txt_file(&mut txt_file, &trailers_metadata)?;
+        txt_file.flush()?;
 
         println!("Created {:?}", &txt_path);
 
         Ok(())
     }
 
-    fn convert_to_feature_files<P: AsRef<Path>>(
+    fn convert_to_feature_files<FS: FileSystem, W: WritableFileSystem, P: AsRef<Path>>(
         &mut self,
+        fs: &mut FS,
+        output_fs: &mut W,
         entries: Vec<usize>,
         output: P,
     ) -> Result<()> {
@@ -316,7 +397,7 @@ impl Files {
             //return Ok(())
         }
 
-        create_dir_all(output.as_ref())?;
+        output_fs.create_dir_all(output.as_ref())?;
 
         let mut reels: Vec<u8> = vec![];
         for i in &entries {
@@ -325,6 +406,9 @@ impl Files {
         }
 
         reels.sort();
+        if self.union {
+            reels.dedup();
+        }
 
         println!(
             "Found {} reels ({})",
@@ -342,8 +426,17 @@ impl Files {
             }
         }
 
+        let mut written: Vec<u8> = vec![];
         for i in entries {
             let e = &self.entries[i];
+            if self.union && written.contains(&e.snd.metadata.reel) {
+                println!(
+                    "Warning: reel {} found more than once, skipping {:?}",
+                    e.snd.metadata.reel, e.snd.path
+                );
+                continue;
+            }
+            written.push(e.snd.metadata.reel);
             let snd_path_from = &e.snd.path;
             let snd_path_to = output.as_ref().join(format!(
                 "r{}t5.{}",
@@ -355,7 +448,10 @@ impl Files {
                 }
             ));
 
-            fs::copy(&snd_path_from, &snd_path_to)?;
+            let mut snd_file_from = fs.open_file(snd_path_from)?;
+            let mut snd_file_to = output_fs.create_file(&snd_path_to)?;
+            std::io::copy(&mut snd_file_from, &mut snd_file_to)?;
+            snd_file_to.flush()?;
 
             println!("Created {:?}", &snd_path_to);
         }
@@ -387,3 +483,71 @@ fn get_hdr_from_snd<D: DirEntry>(entries: &Vec<D>, snd: &Path) -> Option<PathBuf
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memfile::MemoryFileSystem,
+        metadata::SndType,
+        snd::{get_test_header, get_test_snd},
+    };
+
+    fn get_trailer_snd(id: u16, frames: usize) -> Vec<u8> {
+        let mut metadata = get_test_header(id, 14);
+        metadata.snd_type = SndType::Trailer;
+        metadata.title = "TRAILER".to_string();
+        get_test_snd(&metadata, frames)
+    }
+
+    #[test]
+    fn test_extract_trailers_to_memory() {
+        let mut input_fs = MemoryFileSystem::new();
+        input_fs.create_dir_all("/data").unwrap();
+        input_fs
+            .insert_file("/data/trailer1.snd", get_trailer_snd(101, 2))
+            .unwrap();
+        input_fs
+            .insert_file("/data/trailer2.snd", get_trailer_snd(102, 3))
+            .unwrap();
+        input_fs
+            .insert_file("/data/broken.snd", b"not a header".to_vec())
+            .unwrap();
+        let mut output_fs = MemoryFileSystem::new();
+
+        let trailers = Some(Trailers::Ids(TrailerIds {
+            ids: vec![102, 101],
+        }));
+        let warnings = extract_from_dir(
+            &mut input_fs,
+            &mut output_fs,
+            &PathBuf::from("/data"),
+            &PathBuf::from("/out/dts"),
+            &None,
+            &trailers,
+            false,
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("skipping /data/broken.snd: "));
+
+        let mut snd = output_fs.open_file("/out/dts/r14t5.aud").unwrap();
+        let len = snd.len().unwrap();
+        assert_eq!(len, 92 + 5 * 3675);
+        let audio = snd.read_exact_bytes_at(5 * 3675, 92).unwrap();
+        let expected: Vec<u8> = [get_trailer_snd(102, 3), get_trailer_snd(101, 2)]
+            .iter()
+            .flat_map(|t| t[92..].to_vec())
+            .collect();
+        assert_eq!(audio, expected);
+
+        let mut txt = output_fs.open_file("/out/dts/r14trlr.txt").unwrap();
+        let mut content = String::new();
+        txt.read_to_string(&mut content).unwrap();
+        let lines: Vec<&str> = content.lines().skip(2).collect();
+        assert_eq!(
+            lines,
+            vec!["TRAILER\t102\t0\t3\t92", "TRAILER\t101\t0\t2\t11117"]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    io::{Read, Seek, SeekFrom},
+    io::{Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
@@ -70,3 +70,10 @@ pub trait FileSystem {
     fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::File>;
     fn read_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<Self::DirEntry>>;
 }
+
+pub trait WritableFileSystem {
+    type Writer: Write;
+
+    fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()>;
+    fn create_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::Writer>;
+}
@@ -1,26 +1,31 @@
+use std::io::Write;
 use std::path::Path;
 use std::str;
 
 use anyhow::{Result, anyhow};
+use encoding_rs::WINDOWS_1252;
 
-use crate::file::File;
-use crate::metadata::HdrFileMetadata;
+use crate::file::{File, WritableFileSystem};
+use crate::metadata::{HdrFileMetadata, SndFileMetadata};
 use crate::utils::get_title;
 
-const HDR_LEN: u8 = 0xCA;
+pub const HDR_LEN: u8 = 0xCA;
 const HDR_HEADER: [u8; 8] = [0x00, 0x01, 0x00, 0x04, 0x00, 0x44, 0x54, 0x53];
+/// Size of the title field
+const HDR_TITLE_LEN: usize = 18;
 
-pub fn is_hdr_file(file: &mut dyn File) -> bool {
-    if check_hdr_size(file).0 {
-        let bytes = match file.read_bytes(HDR_LEN as usize) {
-            Ok(b) => b,
-            Err(_) => return false,
-        };
-        if check_hdr_magic(&bytes) {
-            return true;
-        }
+/// How likely (0 to 100) the file is a HDR file: the magic alone is not
+/// enough, HDR files always have the same size.
+pub fn get_hdr_confidence(file: &mut dyn File) -> u8 {
+    // Check the magic first, getting the size of a stream means reading all of it
+    let bytes = match file.read_bytes(HDR_LEN as usize) {
+        Ok(b) => b,
+        Err(_) => return 0,
+    };
+    if !check_hdr_magic(&bytes) {
+        return 0;
     }
-    return false;
+    if check_hdr_size(file).0 { 100 } else { 80 }
 }
 
 pub fn decode_hdr_from_file(file: &mut dyn File, path: &Path) -> Result<HdrFileMetadata> {
@@ -46,7 +51,8 @@ pub fn decode_hdr(bytes: &[u8], path: &Path) -> Result<HdrFileMetadata> {
     }
     let title = get_title(&bytes[9..27 /*18*/])?;
     let title = title.trim_matches(char::from(0));
-    let studio = str::from_utf8(&bytes[69..79])?.trim_matches(char::from(0));
+    let studio = get_title(&bytes[69..79])?;
+    let studio = studio.trim_matches(char::from(0));
     let id = u16::from_le_bytes([bytes[79], bytes[80]]);
     let reel = bytes[91];
     Ok(HdrFileMetadata {
@@ -57,6 +63,53 @@ pub fn decode_hdr(bytes: &[u8], path: &Path) -> Result<HdrFileMetadata> {
     })
 }
 
+/// Counterpart of decode_hdr, the bytes it does not read are left empty.
+/// Title and studio are encoded as Windows-1252, like get_title decodes
+/// them, and cut to the number of characters their field holds.
+pub fn encode_hdr(data: &HdrFileMetadata) -> Vec<u8> {
+    let mut bytes = vec![0; HDR_LEN as usize];
+    bytes[0] = HDR_LEN;
+    bytes[1..9].copy_from_slice(&HDR_HEADER);
+    insert_field(&mut bytes[9..27], &data.title);
+    insert_field(&mut bytes[69..79], &data.studio);
+    bytes[79..81].copy_from_slice(&data.id.to_le_bytes());
+    bytes[91] = data.reel;
+    bytes
+}
+
+/// Writes the HDR file matching the header of a reel
+pub fn write_hdr<W: WritableFileSystem>(
+    output_fs: &mut W,
+    path: &Path,
+    metadata: &SndFileMetadata,
+) -> Result<()> {
+    if metadata.title.chars().count() > HDR_TITLE_LEN {
+        println!(
+            "Warning: title cut to {} characters in the HDR file",
+            HDR_TITLE_LEN
+        );
+    }
+    let mut hdr = output_fs.create_file(path)?;
+    hdr.write_all(&encode_hdr(&HdrFileMetadata {
+        id: metadata.id,
+        reel: metadata.reel,
+        title: metadata.title.clone(),
+        studio: metadata.studio.clone().unwrap_or_default(),
+    }))?;
+    hdr.flush()?;
+    println!("Created {:?}", path);
+    Ok(())
+}
+
+/// Characters Windows-1252 does not have are written as '?'
+fn insert_field(field: &mut [u8], value: &str) {
+    let mut utf8 = [0; 4];
+    for (byte, c) in field.iter_mut().zip(value.chars()) {
+        let (bytes, _, had_errors) = WINDOWS_1252.encode(c.encode_utf8(&mut utf8));
+        *byte = if had_errors { b'?' } else { bytes[0] };
+    }
+}
+
 fn check_hdr_size(file: &mut dyn File) -> (bool, u64) {
     match file.len() {
         Ok(len) => (len == HDR_LEN as u64, len),
@@ -67,3 +120,26 @@ fn check_hdr_size(file: &mut dyn File) -> (bool, u64) {
 pub fn check_hdr_magic(bytes: &[u8]) -> bool {
     return bytes[0] == HDR_LEN && bytes[1..].starts_with(&HDR_HEADER);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_accented_title() {
+        let data = HdrFileMetadata {
+            id: 1234,
+            reel: 3,
+            title: "Amélie à Montmartre, été".to_string(),
+            studio: "Zoé".to_string(),
+        };
+        let bytes = encode_hdr(&data);
+        assert_eq!(bytes.len(), HDR_LEN as usize);
+        assert_eq!(bytes[9..12], [b'A', b'm', 0xE9]);
+        let decoded = decode_hdr(&bytes, Path::new("r3.hdr")).unwrap();
+        assert_eq!(decoded.title, "Amélie à Montmartr");
+        assert_eq!(decoded.studio, "Zoé");
+        assert_eq!(decoded.id, 1234);
+        assert_eq!(decoded.reel, 3);
+    }
+}
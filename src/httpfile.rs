@@ -0,0 +1,429 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    net::TcpStream,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::file::File;
+
+const HTTP_PREFIX: &str = "http://";
+const HTTP_DEFAULT_PORT: u16 = 80;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const BLOCK_LEN: u64 = 64 * 1024;
+const CACHED_BLOCKS: usize = 32;
+
+pub fn is_http_url(path: &Path) -> bool {
+    path.to_string_lossy()
+        .to_ascii_lowercase()
+        .starts_with(HTTP_PREFIX)
+}
+
+/// Remote file read with HTTP/1.1 range requests.
+///
+/// Data is fetched in blocks of `BLOCK_LEN` bytes and the most recently used
+/// blocks are kept in memory, so only the parts of the file that are actually
+/// read (headers, directory records...) are downloaded.
+pub struct HttpFile {
+    host: String,
+    port: u16,
+    path: String,
+    len: u64,
+    current: u64,
+    connection: Option<BufReader<TcpStream>>,
+    blocks: VecDeque<(u64, Vec<u8>)>,
+    downloaded: u64,
+}
+
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl HttpFile {
+    pub fn open(url: &str) -> Result<Self> {
+        let (host, port, path) = parse_url(url)?;
+        let mut file = HttpFile {
+            host,
+            port,
+            path,
+            len: 0,
+            current: 0,
+            connection: None,
+            blocks: VecDeque::new(),
+            downloaded: 0,
+        };
+        file.len = file.get_remote_len()?;
+        Ok(file)
+    }
+
+    /// Number of body bytes received from the server so far.
+    #[cfg(test)]
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    fn get_remote_len(&mut self) -> Result<u64> {
+        let response = self.request_range(0, 0)?;
+        match response.status {
+            206 => {
+                let content_range = response
+                    .get_header("Content-Range")
+                    .ok_or(anyhow!("Missing Content-Range header from {}", self.url()))?;
+                parse_content_range_len(content_range)
+            }
+            200 => Err(anyhow!(
+                "Server does not support range requests for {}",
+                self.url()
+            )),
+            416 => Ok(0),
+            status => Err(anyhow!(
+                "Unexpected HTTP status {} for {}",
+                status,
+                self.url()
+            )),
+        }
+    }
+
+    fn host_header(&self) -> String {
+        if self.port == HTTP_DEFAULT_PORT {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("{}{}:{}{}", HTTP_PREFIX, self.host, self.port, self.path)
+    }
+
+    fn connect(&mut self) -> Result<&mut BufReader<TcpStream>> {
+        if self.connection.is_none() {
+            let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+            stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+            stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+            self.connection = Some(BufReader::new(stream));
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    fn request_range(&mut self, start: u64, end: u64) -> Result<HttpResponse> {
+        // The server may have closed an idle keep-alive connection, retry once
+        match self.try_request_range(start, end) {
+            Ok(response) => Ok(response),
+            Err(_) => {
+                self.connection = None;
+                self.try_request_range(start, end)
+            }
+        }
+    }
+
+    fn try_request_range(&mut self, start: u64, end: u64) -> Result<HttpResponse> {
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={}-{}\r\nConnection: keep-alive\r\n\r\n",
+            self.path,
+            self.host_header(),
+            start,
+            end
+        );
+        let connection = self.connect()?;
+        connection.get_mut().write_all(request.as_bytes())?;
+        let mut response = read_response_head(connection)?;
+        if response.status != 206 {
+            // The body may be the whole file if the server ignored the range,
+            // drop the connection instead of reading it
+            self.connection = None;
+            return Ok(response);
+        }
+        read_response_body(connection, &mut response)?;
+        let keep_alive = !response
+            .get_header("Connection")
+            .is_some_and(|c| c.eq_ignore_ascii_case("close"));
+        if !keep_alive {
+            self.connection = None;
+        }
+        self.downloaded += response.body.len() as u64;
+        Ok(response)
+    }
+
+    fn get_block(&mut self, index: u64) -> std::io::Result<&[u8]> {
+        if let Some(position) = self.blocks.iter().position(|(i, _)| *i == index) {
+            let block = self.blocks.remove(position).unwrap();
+            self.blocks.push_front(block);
+        } else {
+            let start = index * BLOCK_LEN;
+            let end = (start + BLOCK_LEN).min(self.len) - 1;
+            let response = self
+                .request_range(start, end)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            if response.status != 206 || response.body.len() as u64 != end - start + 1 {
+                return Err(std::io::Error::other(format!(
+                    "Unexpected response (HTTP status {}, {} bytes) for range {}-{} of {}",
+                    response.status,
+                    response.body.len(),
+                    start,
+                    end,
+                    self.url()
+                )));
+            }
+            if self.blocks.len() == CACHED_BLOCKS {
+                self.blocks.pop_back();
+            }
+            self.blocks.push_front((index, response.body));
+        }
+        Ok(&self.blocks.front().unwrap().1)
+    }
+}
+
+impl File for HttpFile {
+    fn len(&mut self) -> Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl Read for HttpFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.current >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.current / BLOCK_LEN;
+        let offset = (self.current % BLOCK_LEN) as usize;
+        let block = self.get_block(index)?;
+        let count = buf.len().min(block.len() - offset);
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for HttpFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => self.len as i64 + from_end,
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+fn parse_url(url: &str) -> Result<(String, u16, String)> {
+    if !url.to_ascii_lowercase().starts_with(HTTP_PREFIX) {
+        return Err(anyhow!(
+            "Unsupported URL (only http:// is supported): {}",
+            url
+        ));
+    }
+    let rest = &url[HTTP_PREFIX.len()..];
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| anyhow!("Invalid port in URL: {}", url))?,
+        ),
+        None => (authority, HTTP_DEFAULT_PORT),
+    };
+    if host.is_empty() {
+        return Err(anyhow!("Missing host in URL: {}", url));
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn parse_content_range_len(value: &str) -> Result<u64> {
+    // Content-Range: bytes 0-0/12345
+    match value.rsplit_once('/') {
+        Some((_, len)) => Ok(len.trim().parse::<u64>()?),
+        None => Err(anyhow!("Could not parse Content-Range header: {}", value)),
+    }
+}
+
+/// Reads the status line and the headers of a response, leaving the body in
+/// `reader`.
+fn read_response_head(reader: &mut BufReader<TcpStream>) -> Result<HttpResponse> {
+    let mut status_line = String::new();
+    if reader.read_line(&mut status_line)? == 0 {
+        return Err(anyhow!("Connection closed by server"));
+    }
+    let status = status_line
+        .split_ascii_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or(anyhow!("Invalid HTTP status line: {}", status_line.trim()))?;
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: vec![],
+    })
+}
+
+fn read_response_body(
+    reader: &mut BufReader<TcpStream>,
+    response: &mut HttpResponse,
+) -> Result<()> {
+    if response
+        .get_header("Transfer-Encoding")
+        .is_some_and(|t| t.eq_ignore_ascii_case("chunked"))
+    {
+        return Err(anyhow!("Chunked HTTP responses are not supported"));
+    }
+    let len = match response.get_header("Content-Length") {
+        Some(l) => l.parse::<usize>()?,
+        None => return Err(anyhow!("Missing Content-Length header in HTTP response")),
+    };
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    response.body = body;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Minimal HTTP/1.1 server answering range requests for `data`.
+    fn serve(data: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                loop {
+                    let mut range = None;
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).unwrap();
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        if let Some(r) = header.strip_prefix("Range: bytes=") {
+                            let (start, end) = r.split_once('-').unwrap();
+                            let start = start.parse::<usize>().unwrap();
+                            let end = end.parse::<usize>().unwrap();
+                            range = Some((start, end.min(data.len() - 1)));
+                        }
+                    }
+                    let (start, end) = range.unwrap();
+                    let body = &data[start..=end];
+                    let head = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                        start,
+                        end,
+                        data.len(),
+                        body.len()
+                    );
+                    writer.write_all(head.as_bytes()).unwrap();
+                    writer.write_all(body).unwrap();
+                }
+            }
+        });
+        format!("http://{}/disc.iso", address)
+    }
+
+    /// Server sending `head` to every request and keeping the connection
+    /// open without sending any body.
+    fn serve_head(head: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut streams = vec![];
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                stream.write_all(head.as_bytes()).unwrap();
+                streams.push(stream);
+            }
+        });
+        format!("http://{}/disc.iso", address)
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("http://archive:8080/dts/disc.iso").unwrap(),
+            ("archive".to_string(), 8080, "/dts/disc.iso".to_string())
+        );
+        assert_eq!(
+            parse_url("http://archive").unwrap(),
+            ("archive".to_string(), 80, "/".to_string())
+        );
+        assert!(parse_url("https://archive/disc.iso").is_err());
+    }
+
+    #[test]
+    fn test_read_ranges() {
+        let data: Vec<u8> = (0..(BLOCK_LEN * 10)).map(|i| (i % 251) as u8).collect();
+        let url = serve(data.clone());
+        let mut file = HttpFile::open(&url).unwrap();
+
+        assert_eq!(file.len().unwrap(), data.len() as u64);
+
+        let at = BLOCK_LEN * 3 - 10;
+        let bytes = file.read_exact_bytes_at(20, at).unwrap();
+        assert_eq!(bytes, data[at as usize..at as usize + 20]);
+
+        let bytes = file.read_bytes(92).unwrap();
+        assert_eq!(bytes, data[..92]);
+
+        // Only the length probe and the three blocks touched were downloaded
+        assert_eq!(file.downloaded(), 1 + 3 * BLOCK_LEN);
+    }
+
+    #[test]
+    fn test_range_not_supported() {
+        // The body is never sent, reading it would block until the timeout
+        let url = serve_head("HTTP/1.1 200 OK\r\nContent-Length: 1000000000\r\n\r\n");
+        let e = HttpFile::open(&url).err().unwrap();
+        assert!(e.to_string().contains("does not support range requests"));
+
+        let url = serve_head("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-0/10\r\n\r\n");
+        let e = HttpFile::open(&url).err().unwrap();
+        assert!(e.to_string().contains("Missing Content-Length"));
+    }
+}
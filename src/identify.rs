@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    cd::CdTreeEntries,
+    detect::{DirType, FileType, FileTypeCandidate, get_dir_type, get_file_type_candidates},
+    file::{File, FileSystem},
+    hdd::decode_hdd_img_from_file,
+    hdr::decode_hdr_from_file,
+    httpfile::is_http_url,
+    info::open_input_file,
+    iso::decode_iso_from_file,
+    osfile::OsFileSystem,
+    probe::MIN_CONTENT_CONFIDENCE,
+    snd::decode_snd_header_from_file,
+    streamfile::is_stdin_path,
+};
+
+/// Candidates this close to the best one are reported as ambiguous
+const AMBIGUITY_MARGIN: u8 = 20;
+
+pub fn identify_paths(paths: &[PathBuf], verbose: bool) -> Result<()> {
+    for path in paths {
+        identify_path(path, verbose)?;
+        println!();
+    }
+    Ok(())
+}
+
+fn identify_path(path: &Path, verbose: bool) -> Result<()> {
+    let mut os_fs = OsFileSystem;
+    if !is_stdin_path(path) && !is_http_url(path) && os_fs.is_dir(path) {
+        let entries = os_fs.read_dir(path)?;
+        let description = match get_dir_type(&mut os_fs, &entries, verbose)? {
+            DirType::DiscTree(cd) => describe_cd(&cd),
+            DirType::Regular => "regular directory".to_string(),
+        };
+        println!("{}: {}", path.display(), description);
+        return Ok(());
+    }
+
+    let mut file = open_input_file(path)?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let candidates = get_file_type_candidates(file.as_mut(), path, true, verbose);
+    let best = candidates.iter().position(|c| c.is_acceptable());
+    match best {
+        Some(i) => println!(
+            "{}: {} ({}%)",
+            path.display(),
+            candidates[i].probe.name,
+            candidates[i].confidence()
+        ),
+        None => println!("{}: not recognized", path.display()),
+    }
+
+    println!("  Candidates:");
+    if candidates.is_empty() {
+        println!("    none, no probe matched the content or the extension");
+    }
+    for c in &candidates {
+        println!(
+            "    {}: {}% (content {}%{})",
+            c.probe.name,
+            c.confidence(),
+            c.content_confidence,
+            if c.extension_match {
+                format!(", extension .{}", extension)
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    let Some(best) = best else {
+        return Ok(());
+    };
+    print_ambiguity(&candidates, best, &extension);
+
+    println!("  Container stack:");
+    print_container_stack(file, &candidates[best], path, verbose)
+}
+
+/// Explains why the chosen type could be wrong: the extension says otherwise,
+/// only the extension was recognized, or other formats score almost as well.
+fn print_ambiguity(candidates: &[FileTypeCandidate], best: usize, extension: &str) {
+    let chosen = &candidates[best];
+    if !chosen.extension_match
+        && let Some(other) = candidates.iter().find(|c| c.extension_match)
+    {
+        println!(
+            "  Note: the .{} extension is used for {} files, but the content is recognized as {} ({}% against {}%)",
+            extension,
+            other.probe.name,
+            chosen.probe.name,
+            chosen.content_confidence,
+            other.content_confidence
+        );
+    }
+    if chosen.content_confidence < MIN_CONTENT_CONFIDENCE {
+        println!(
+            "  Note: recognized from its extension, the content only matches {} at {}%",
+            chosen.probe.name, chosen.content_confidence
+        );
+    }
+    for (i, c) in candidates.iter().enumerate() {
+        if i != best
+            && c.is_acceptable()
+            && c.confidence() + AMBIGUITY_MARGIN >= chosen.confidence()
+        {
+            println!(
+                "  Note: also matches {} ({}%), {} was chosen as it {}",
+                c.probe.name,
+                c.confidence(),
+                chosen.probe.name,
+                if c.confidence() == chosen.confidence() {
+                    "is probed first"
+                } else {
+                    "scores higher"
+                }
+            );
+        }
+    }
+}
+
+/// Opens every container (virtual disk, raw CD image) down to the image they
+/// hold, and describes what is found in the last one.
+fn print_container_stack(
+    mut file: Box<dyn File>,
+    candidate: &FileTypeCandidate,
+    path: &Path,
+    verbose: bool,
+) -> Result<()> {
+    let mut probe = candidate.probe;
+    let mut file_type = candidate.file_type;
+    let mut layer = 1;
+    while let Some(unwrap) = probe.unwrap {
+        let (description, inner) = unwrap(file, verbose)?;
+        println!("    {}. {}", layer, description);
+        file = inner;
+        layer += 1;
+        let inner_candidates = get_file_type_candidates(file.as_mut(), "", false, verbose);
+        match inner_candidates.into_iter().find(|c| c.is_acceptable()) {
+            Some(c) => {
+                probe = c.probe;
+                file_type = c.file_type;
+            }
+            None => {
+                println!("    {}. unrecognized content", layer);
+                return Ok(());
+            }
+        }
+    }
+    println!(
+        "    {}. {}",
+        layer,
+        describe_content(file, probe.name, file_type, path, verbose)
+    );
+    Ok(())
+}
+
+fn describe_content(
+    mut file: Box<dyn File>,
+    name: &str,
+    file_type: FileType,
+    path: &Path,
+    verbose: bool,
+) -> String {
+    let details = match file_type {
+        FileType::Iso => decode_iso_from_file(file, path, verbose).map(|cd| describe_cd(&cd)),
+        FileType::Hdr => decode_hdr_from_file(file.as_mut(), path)
+            .map(|data| format!("'{}', id {}", data.title, data.id)),
+        FileType::Aud | FileType::Aue | FileType::Snd => {
+            decode_snd_header_from_file(file.as_mut(), path).map(|data| {
+                format!(
+                    "{} {}, reel {} of '{}', id {}, {} tracks",
+                    data.revision,
+                    if data.encryption_key.is_some() {
+                        "AUE"
+                    } else {
+                        "AUD"
+                    },
+                    data.reel,
+                    data.title,
+                    data.id,
+                    data.tracks
+                )
+            })
+        }
+        FileType::HddImg => decode_hdd_img_from_file(file, verbose).map(|fs| match fs {
+            Some(_) => "DTS content partition found".to_string(),
+            None => "no DTS content partition".to_string(),
+        }),
+        _ => return name.to_string(),
+    };
+    match details {
+        Ok(d) => format!("{}: {}", name, d),
+        Err(e) => format!("{}, could not be decoded: {}", name, e),
+    }
+}
+
+fn describe_cd(cd: &CdTreeEntries) -> String {
+    format!(
+        "DTS CD with {} reels{}",
+        cd.reels.len(),
+        if cd.trailers.is_some() {
+            " and trailers"
+        } else {
+            ""
+        }
+    )
+}
@@ -1,27 +1,59 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use itertools::Itertools;
 
 use crate::{
+    audio::AudioLayout,
+    binfile::BinFile,
     cd::CdTreeEntries,
     detect::{DirType, FileType, SndFileType, get_dir_type, get_file_type},
-    file::{DirEntry, File, FileSystem},
-    hdd::decode_hdd_img_from_file,
+    file::{self, DirEntry, File, FileSystem},
+    hdd::{decode_hdd_img_from_file, is_hdd_img_file},
     hdr::decode_hdr_from_file,
+    httpfile::{HttpFile, is_http_url},
     iso::decode_iso_from_file,
     json::{EntryJson, save_json},
-    osfile::OsFileSystem,
+    metadata::{DTS_FRAME_RATE, SndFileMetadata, SndType},
+    osfile::{OsFile, OsFileSystem, is_device_path},
+    rescue::{analyze_rescued_iso, open_rescued_image, print_rescue_report},
     snd::decode_snd_header_from_file,
     squash::decode_squashfs_from_file,
+    streamfile::{StreamFile, is_stdin_path},
+    timecode::{FILM_FRAMES_PER_FOOT, Timecode},
     trailers::decode_trailers_from_txt_file,
+    unionfile::UnionFileSystem,
+    vdisk::decode_virtual_disk_from_file,
 };
 
-pub fn print_info(paths: &[PathBuf], output_json: Option<PathBuf>, verbose: bool) -> Result<()> {
-    let json_entries: Vec<EntryJson> = paths
-        .iter()
-        .map(|path| print_path_info(&path, verbose))
-        .process_results(|e| e.flatten().collect())?;
+/// Longest reel of 35 mm film
+const MAX_REEL_FEET: u64 = 2000;
+
+pub fn print_info(
+    paths: &[PathBuf],
+    output_json: Option<PathBuf>,
+    union: bool,
+    mapfile: Option<PathBuf>,
+    verbose: bool,
+) -> Result<()> {
+    let mut json_entries: Vec<EntryJson> = if let Some(mapfile) = mapfile {
+        if union || paths.len() != 1 {
+            return Err(anyhow!("A mapfile can only be used with a single input"));
+        }
+        print_rescued_path_info(&paths[0], &mapfile, verbose)?
+    } else if union {
+        print_union_info(paths, verbose)?
+    } else {
+        paths
+            .iter()
+            .map(|path| print_path_info(&path, verbose))
+            .process_results(|e| e.flatten().collect())?
+    };
+
+    print_feature_runtimes(&mut json_entries);
 
     match output_json {
         Some(p) => save_json(p, json_entries, verbose),
@@ -32,13 +64,77 @@ pub fn print_info(paths: &[PathBuf], output_json: Option<PathBuf>, verbose: bool
 fn print_path_info(path: &Path, verbose: bool) -> Result<Vec<EntryJson>> {
     let mut os_fs = OsFileSystem;
 
-    if os_fs.is_dir(path) {
+    if !is_stdin_path(path) && !is_http_url(path) && os_fs.is_dir(path) {
         return print_dir_info(&mut os_fs, &path, verbose);
-    } else if os_fs.is_file(path) {
-        return print_file_info(Box::new(os_fs.open_file(path)?), path, verbose);
     }
+    print_file_info(open_input_file(path)?, path, verbose)
+}
+
+/// Opens a file input: stdin, URL, regular file or device.
+pub fn open_input_file(path: &Path) -> Result<Box<dyn File>> {
+    let mut os_fs = OsFileSystem;
 
-    unreachable!()
+    if is_stdin_path(path) {
+        return Ok(Box::new(StreamFile::from_stdin()));
+    }
+    if is_http_url(path) {
+        return Ok(Box::new(HttpFile::open(&path.to_string_lossy())?));
+    }
+    if os_fs.is_file(path) {
+        return Ok(Box::new(os_fs.open_file(path)?));
+    } else if is_device_path(path) {
+        return Ok(Box::new(OsFile::open_device(path)?));
+    }
+
+    Err(anyhow!("Could not open input {}", path.display()))
+}
+
+fn print_rescued_path_info(path: &Path, mapfile: &Path, verbose: bool) -> Result<Vec<EntryJson>> {
+    let file = open_rescued_image(path, mapfile)?;
+    let info = print_file_info(Box::new(file), path, verbose);
+    if info.is_err() {
+        println!();
+    }
+    let report = analyze_rescued_iso(open_rescued_image(path, mapfile)?, verbose)?;
+    print_rescue_report(&report);
+    info
+}
+
+fn print_union_info(paths: &[PathBuf], verbose: bool) -> Result<Vec<EntryJson>> {
+    let mut union = UnionFileSystem::from_paths(paths, verbose)?;
+
+    println!("Library made of {} sources:", paths.len());
+    for (path, name) in union.source_names() {
+        println!("  {}: {}", path.display(), name);
+    }
+    println!();
+
+    print_union_conflicts(&mut union)?;
+
+    let mut entries: Vec<_> = union
+        .read_dir("/")?
+        .into_iter()
+        .filter(|e| e.file_type().is_ok_and(|t| t == file::FileType::File))
+        .collect();
+    entries.sort_by_key(|e| e.file_name());
+    print_regular_dir_info(&mut union, &entries, verbose)
+}
+
+pub fn print_union_conflicts(union: &mut UnionFileSystem) -> Result<()> {
+    let conflicts = union.find_conflicts()?;
+    for c in &conflicts {
+        println!(
+            "Warning: conflict, id {} reel {} found in more than one source:",
+            c.id, c.reel
+        );
+        for p in &c.paths {
+            println!("  {}", p.display());
+        }
+    }
+    if !conflicts.is_empty() {
+        println!();
+    }
+    Ok(())
 }
 
 fn print_dir_info<FS: FileSystem, P: AsRef<Path>>(
@@ -102,17 +198,21 @@ fn print_disc_dir_info(disc: CdTreeEntries, verbose: bool) -> Result<Vec<EntryJs
         }
     );
     // TODO
-    print_files_info(disc.reels, verbose)?;
-    match trailers {
-        Some(m) => {
-            println!("Trailers:");
-            for t in m.entries {
-                println!("  Id: {}, Title: {}", t.id, t.title);
-            }
+    let mut json_entries = print_files_info(disc.reels, verbose)?;
+    if let Some(m) = trailers {
+        println!("Trailers:");
+        for t in &m.entries {
+            println!(
+                "  Id: {}, Title: {}, Duration: {} ({} ft)",
+                t.id,
+                t.title,
+                t.duration().to_clock_string(),
+                t.duration().to_feet_frames()
+            );
         }
-        None => (),
+        json_entries.extend(m.entries.iter().map(|t| t.to_entry()));
     }
-    Ok(vec![])
+    Ok(json_entries)
 }
 
 fn print_entries_info<FS: FileSystem, D: DirEntry>(
@@ -123,16 +223,21 @@ fn print_entries_info<FS: FileSystem, D: DirEntry>(
 where
     <FS as FileSystem>::File: 'static,
 {
+    // A file that can not be read is reported and the others still listed
     entries
         .iter()
         .map(|d| {
             let path = d.path()?;
             println!("{:?}", path);
-            let r = print_file_info(Box::new(fs.open_file(path.as_path())?), &path, verbose);
-            if r.is_ok() {
-                println!();
-            }
-            return r;
+            let r = fs
+                .open_file(path.as_path())
+                .and_then(|f| print_file_info(Box::new(f), &path, verbose))
+                .unwrap_or_else(|e| {
+                    println!("Warning: {}", e);
+                    vec![]
+                });
+            println!();
+            Ok(r)
         })
         .process_results(|e| e.flatten().collect())
 }
@@ -175,9 +280,11 @@ fn print_file_info(mut file: Box<dyn File>, path: &Path, verbose: bool) -> Resul
         FileType::Hdr => print_hdr_info(file.as_mut(), path),
         FileType::Snd => print_snd_header_info(file.as_mut(), path, None),
         FileType::Iso => print_iso_info(file, path, verbose),
+        FileType::Bin => print_iso_info(Box::new(BinFile::from_file(file)?), path, verbose),
         FileType::SquashFs => print_squashfs_info(file, verbose),
         FileType::HddImg => print_hdd_img_info(file, verbose),
         FileType::PartitionImg => print_partition_img_info(file),
+        FileType::VirtualDisk => print_virtual_disk_info(file, path, verbose),
     };
 }
 
@@ -228,6 +335,9 @@ fn print_snd_header_info(
         data.revision,
         path.display()
     );
+    for w in &data.warnings {
+        println!("  Warning: {}", w);
+    }
     println!("  Id: {}", data.id);
     println!("  Title: {}", data.title);
     println!("  Reel: {}", data.reel);
@@ -253,7 +363,7 @@ fn print_snd_header_info(
             }
         }
     }
-    if let Some(studio) = data.studio {
+    if let Some(studio) = &data.studio {
         println!("  Studio: {}", studio);
     }
     println!("  Optical Backup: {}", data.optical_backup);
@@ -264,6 +374,7 @@ fn print_snd_header_info(
     if let Some(end) = &data.end_offset {
         println!("  End: {}", end);
     }
+    let length = print_reel_length(&data, get_audio_frames(file));
     if let Some(key) = data.encryption_key {
         println!("  Encrypted: {}, key={:#04x}", true, key);
     } else {
@@ -277,7 +388,141 @@ fn print_snd_header_info(
             );
         }
     }
-    Ok(vec![])
+    Ok(vec![data.to_entry(length)])
+}
+
+/// Complete frames after the header, None when they can not be counted (like
+/// from a stream)
+fn get_audio_frames(file: &mut dyn File) -> Option<u64> {
+    let layout = AudioLayout::from_file(file).ok()?;
+    let len = file.len().ok()?;
+    Some(len.saturating_sub(layout.header_len) / layout.frame_len())
+}
+
+/// Duration between the offsets of a reel and its length, checked against its
+/// audio
+struct ReelLength {
+    /// None without offsets or when the end is not after the start
+    duration: Option<Timecode>,
+    /// Between the offsets, or of the audio when they are not usable
+    length: Option<Timecode>,
+    warnings: Vec<String>,
+}
+
+/// Prints the length of the audio and the duration between the offsets, with
+/// their footage, and warns about offsets not matching the audio and about
+/// feature reels too long for a reel of film. Returns the duration of the
+/// reel: between its offsets, or of its audio when they are not usable.
+fn print_reel_length(data: &SndFileMetadata, audio_frames: Option<u64>) -> Option<Timecode> {
+    let audio = audio_frames.map(|f| Timecode::new(f, DTS_FRAME_RATE));
+    if let Some(audio) = audio {
+        println!(
+            "  Audio length: {} ({} ft)",
+            audio.to_clock_string(),
+            audio.to_feet_frames()
+        );
+    }
+    let reel_length = get_reel_length(data, audio);
+    if let Some(duration) = reel_length.duration {
+        println!(
+            "  Duration: {} ({} ft)",
+            duration.to_clock_string(),
+            duration.to_feet_frames()
+        );
+    }
+    for w in &reel_length.warnings {
+        println!("  Warning: {}", w);
+    }
+    reel_length.length
+}
+
+fn get_reel_length(data: &SndFileMetadata, audio: Option<Timecode>) -> ReelLength {
+    let mut warnings = vec![];
+    let mut duration = data.duration();
+    if let (Some(start), Some(end)) = (data.start_offset, data.end_offset)
+        && end.to_frames() <= start.to_frames()
+    {
+        warnings.push(format!(
+            "the end offset ({}) is not after the start offset ({})",
+            end, start
+        ));
+        duration = None;
+    }
+    if let (Some(duration), Some(audio)) = (duration, audio)
+        && duration.count() > audio.count()
+    {
+        warnings.push(format!(
+            "the offsets span {}, more than the {} of audio",
+            duration.to_clock_string(),
+            audio.to_clock_string()
+        ));
+    }
+    let length = duration.or(audio);
+    if let SndType::Feature = data.snd_type
+        && let Some(length) = length
+        && length.to_feet_frames().to_frames() > MAX_REEL_FEET * FILM_FRAMES_PER_FOOT
+    {
+        warnings.push(format!(
+            "{} ft, longer than a {} ft reel of film",
+            length.to_feet_frames(),
+            MAX_REEL_FEET
+        ));
+    }
+    ReelLength {
+        duration,
+        length,
+        warnings,
+    }
+}
+
+/// Total of the reels of each feature, the ones of unknown length left out.
+/// The HDR and SND files of a reel give separate entries, each reel number is
+/// counted once. The runtime is set on every entry of the feature.
+fn print_feature_runtimes(entries: &mut [EntryJson]) {
+    let mut features: BTreeMap<u16, (String, BTreeMap<u8, Option<u64>>)> = BTreeMap::new();
+    for entry in entries.iter() {
+        let EntryJson::Feature(feature) = entry else {
+            continue;
+        };
+        let (_, reels) = features
+            .entry(feature.id)
+            .or_insert_with(|| (feature.title.clone(), BTreeMap::new()));
+        for reel in &feature.reels {
+            let frames = reels.entry(reel.number).or_default();
+            *frames = frames.or(reel.frames);
+        }
+    }
+
+    for (id, (title, reels)) in features {
+        let known: Vec<(u8, u64)> = reels
+            .iter()
+            .filter_map(|(number, frames)| frames.map(|f| (*number, f)))
+            .collect();
+        if known.is_empty() {
+            continue;
+        }
+        let runtime = Timecode::new(known.iter().map(|(_, f)| f).sum(), DTS_FRAME_RATE);
+        println!(
+            "Feature {} ({}), reels {}: runtime {} ({} ft){}",
+            id,
+            title,
+            known.iter().map(|(number, _)| number).join(", "),
+            runtime.to_clock_string(),
+            runtime.to_feet_frames(),
+            if known.len() < reels.len() {
+                " without the reels of unknown length"
+            } else {
+                ""
+            }
+        );
+        for entry in entries.iter_mut() {
+            if let EntryJson::Feature(feature) = entry
+                && feature.id == id
+            {
+                feature.runtime = Some(runtime.to_clock_string());
+            }
+        }
+    }
 }
 
 fn print_squashfs_info(file: Box<dyn File>, verbose: bool) -> Result<Vec<EntryJson>> {
@@ -302,7 +547,115 @@ fn print_hdd_img_info(file: Box<dyn File>, verbose: bool) -> Result<Vec<EntryJso
     }
 }
 
+fn print_virtual_disk_info(
+    file: Box<dyn File>,
+    path: &Path,
+    verbose: bool,
+) -> Result<Vec<EntryJson>> {
+    let mut disk = decode_virtual_disk_from_file(file, verbose)?;
+    println!("{} virtual disk: {}", disk.format_name(), path.display());
+    if !is_hdd_img_file(&mut disk) {
+        return Err(anyhow!(
+            "Could not find a partition table in virtual disk {}",
+            path.display()
+        ));
+    }
+    print_hdd_img_info(Box::new(disk), verbose)
+}
+
 fn print_partition_img_info(_file: Box<dyn File>) -> Result<Vec<EntryJson>> {
     println!("EXT234");
     Ok(vec![])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::{HdrFileMetadata, Offset},
+        snd::get_test_header,
+    };
+
+    fn get_reel(id: u16, reel: u8, start: u64, end: u64) -> SndFileMetadata {
+        let mut data = get_test_header(id, reel);
+        data.start_offset = Some(Offset::from_frames(start));
+        data.end_offset = Some(Offset::from_frames(end));
+        data
+    }
+
+    fn frames(count: u64) -> Option<Timecode> {
+        Some(Timecode::new(count, DTS_FRAME_RATE))
+    }
+
+    #[test]
+    fn test_reel_length() {
+        // 2000 ft at 24 fps are 1333.33 s, 40000 DTS frames
+        let reel = get_reel(1, 1, 100, 100 + 39_990);
+        let length = get_reel_length(&reel, frames(40_000));
+        assert_eq!(length.length, frames(39_990));
+        assert!(length.warnings.is_empty());
+
+        let reel = get_reel(1, 1, 100, 100 + 40_050);
+        let length = get_reel_length(&reel, frames(40_050));
+        assert_eq!(length.warnings.len(), 1);
+        assert!(length.warnings[0].contains("2000 ft"));
+
+        // Trailers are not reels of film
+        let mut trailer = get_reel(1, 14, 0, 40_050);
+        trailer.snd_type = SndType::Trailer;
+        assert!(get_reel_length(&trailer, None).warnings.is_empty());
+
+        // The audio is used when the offsets are not
+        let reel = get_reel(1, 1, 300, 200);
+        let length = get_reel_length(&reel, frames(500));
+        assert_eq!(length.duration, None);
+        assert_eq!(length.length, frames(500));
+        assert_eq!(length.warnings.len(), 1);
+        assert!(length.warnings[0].contains("not after the start offset"));
+
+        let reel = get_reel(1, 1, 0, 600);
+        let length = get_reel_length(&reel, frames(500));
+        assert_eq!(length.length, frames(600));
+        assert_eq!(length.warnings.len(), 1);
+        assert!(length.warnings[0].contains("more than the"));
+    }
+
+    #[test]
+    fn test_feature_runtimes() {
+        let hdr = HdrFileMetadata {
+            id: 1234,
+            reel: 1,
+            title: "FEATURE".to_string(),
+            studio: String::new(),
+        };
+        let mut entries = vec![
+            hdr.to_entry(),
+            get_reel(1234, 1, 0, 100).to_entry(frames(100)),
+            get_reel(1234, 2, 0, 250).to_entry(frames(250)),
+            get_reel(1234, 3, 0, 0).to_entry(None),
+            get_reel(99, 1, 0, 30).to_entry(frames(30)),
+            get_reel(98, 1, 0, 0).to_entry(None),
+        ];
+        print_feature_runtimes(&mut entries);
+
+        let runtimes: Vec<(u16, Option<String>)> = entries
+            .iter()
+            .filter_map(|e| match e {
+                EntryJson::Feature(f) => Some((f.id, f.runtime.clone())),
+                EntryJson::Trailer(_) => None,
+            })
+            .collect();
+        let runtime = |count| Some(Timecode::new(count, DTS_FRAME_RATE).to_clock_string());
+        assert_eq!(
+            runtimes,
+            vec![
+                (1234, runtime(350)),
+                (1234, runtime(350)),
+                (1234, runtime(350)),
+                (1234, runtime(350)),
+                (99, runtime(30)),
+                (98, None),
+            ]
+        );
+    }
+}
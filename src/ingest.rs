@@ -0,0 +1,191 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::{
+    file::{File, FileSystem, WritableFileSystem},
+    hdr::write_hdr,
+    metadata::SndFileMetadata,
+    osfile::OsFileSystem,
+    platter::copy_bytes,
+    snd::{decode_snd_header_from_file, get_snd_file_stem},
+    unionfile::UnionFileSystem,
+};
+
+/// Converts the feature of a DTS CD (a disc tree, an ISO file or an image) into
+/// the SND and HDR files of an XD10 library. Reels are the same on both, they
+/// are copied as they are.
+pub fn ingest_feature(input: &Path, output: &Path, verbose: bool) -> Result<()> {
+    let mut union = UnionFileSystem::from_paths(&[input], verbose)?;
+    let mut output_fs = OsFileSystem;
+    ingest_reels(&mut union, input, &mut output_fs, output, verbose)
+}
+
+/// Copies the feature reels of the union, the union already starts at the DTS
+/// directory of a disc, copies of a disc tree are also searched in their
+/// subdirectories.
+fn ingest_reels<W: WritableFileSystem>(
+    fs: &mut UnionFileSystem,
+    input: &Path,
+    output_fs: &mut W,
+    output: &Path,
+    verbose: bool,
+) -> Result<()> {
+    let re = Regex::new(r"^r([1-9]|1[0-3])t5\.au[de]$").unwrap();
+    let mut reels: Vec<(SndFileMetadata, PathBuf)> = vec![];
+    let mut has_trailers = false;
+    for path in fs.find_audio_files()? {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        if name.starts_with("r14t5.") {
+            has_trailers = true;
+        }
+        if !re.is_match(&name) {
+            continue;
+        }
+        let mut file = fs.open_file(&path)?;
+        let metadata = decode_snd_header_from_file(&mut file, &path)?;
+        reels.push((metadata, path));
+    }
+    reels.sort_by_key(|(m, _)| m.reel);
+    let Some((first, _)) = reels.first() else {
+        return Err(anyhow!("No feature reel found in {}", input.display()));
+    };
+    println!(
+        "Found {} (id {}), reels {}",
+        first.title,
+        first.id,
+        reels.iter().map(|(m, _)| m.reel).join(", ")
+    );
+    check_reels(&reels)?;
+
+    output_fs.create_dir_all(output)?;
+    for (metadata, path) in &reels {
+        let mut file = fs.open_file(path)?;
+        let snd_path = output
+            .join(get_snd_file_stem(metadata.id, metadata.reel))
+            .with_extension("snd");
+        let mut snd_file = output_fs.create_file(&snd_path)?;
+        let mut md5 = md5::Context::new();
+        let len = file.len()?;
+        copy_bytes(&mut file, 0, len, &mut snd_file, &mut md5)?;
+        snd_file.flush()?;
+        if verbose {
+            println!(
+                "ingest_feature: reel {} from {:?}, md5 {:x}",
+                metadata.reel,
+                path,
+                md5.finalize()
+            );
+        }
+        println!("Created {:?}", snd_path);
+        write_hdr(output_fs, &snd_path.with_extension("hdr"), metadata)?;
+    }
+    if has_trailers {
+        println!("The trailers of the disc can be converted with `trailers --split`");
+    }
+    Ok(())
+}
+
+/// The reels of a library feature share one id
+fn check_reels(reels: &[(SndFileMetadata, PathBuf)]) -> Result<()> {
+    for pair in reels.windows(2) {
+        let ((a, a_path), (b, b_path)) = (&pair[0], &pair[1]);
+        if a.reel == b.reel {
+            return Err(anyhow!(
+                "Reel {} found more than once: {:?} and {:?}",
+                a.reel,
+                a_path,
+                b_path
+            ));
+        }
+        if a.id != b.id {
+            return Err(anyhow!(
+                "Reels {} and {} have different ids ({} and {})",
+                a.reel,
+                b.reel,
+                a.id,
+                b.id
+            ));
+        }
+    }
+    if let Some((last, _)) = reels.last()
+        && reels.len() != last.reel as usize
+    {
+        println!("Warning: Seems like some reels are missing");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, process};
+
+    use super::*;
+    use crate::{
+        file::DirEntry,
+        hdr::decode_hdr_from_file,
+        memfile::MemoryFileSystem,
+        snd::{get_test_header, get_test_snd},
+    };
+
+    fn get_reel(id: u16, reel: u8) -> Vec<u8> {
+        get_test_snd(&get_test_header(id, reel), 2)
+    }
+
+    #[test]
+    fn test_ingest_cd_tree() {
+        // A copy of a disc on disk, the union opens it like the ingest command
+        let cd = std::env::temp_dir().join(format!("dts-tools-ingest-{}", process::id()));
+        fs::create_dir_all(cd.join("dts")).unwrap();
+        fs::write(cd.join("dts.exe"), []).unwrap();
+        fs::write(cd.join("dts/r2t5.aud"), get_reel(1234, 2)).unwrap();
+        fs::write(cd.join("dts/r1t5.aud"), get_reel(1234, 1)).unwrap();
+        fs::write(cd.join("dts/r14t5.aud"), get_reel(1045, 14)).unwrap();
+        let mut union = UnionFileSystem::from_paths(&[&cd], false).unwrap();
+        let mut output_fs = MemoryFileSystem::new();
+
+        let result = ingest_reels(
+            &mut union,
+            &cd,
+            &mut output_fs,
+            Path::new("/library"),
+            false,
+        );
+        fs::remove_dir_all(&cd).unwrap();
+        result.unwrap();
+
+        let mut names: Vec<String> = output_fs
+            .read_dir("/library")
+            .unwrap()
+            .iter()
+            .map(|e| e.file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["1234_r1.hdr", "1234_r1.snd", "1234_r2.hdr", "1234_r2.snd"]
+        );
+        for reel in [1, 2] {
+            let snd_path = PathBuf::from(format!("/library/1234_r{}.snd", reel));
+            let mut snd = output_fs.open_file(&snd_path).unwrap();
+            let mut data = vec![];
+            snd.read_to_end(&mut data).unwrap();
+            assert_eq!(data, get_reel(1234, reel));
+
+            let hdr_path = snd_path.with_extension("hdr");
+            let mut hdr = output_fs.open_file(&hdr_path).unwrap();
+            let hdr = decode_hdr_from_file(&mut hdr, &hdr_path).unwrap();
+            assert_eq!((hdr.id, hdr.reel), (1234, reel));
+            assert_eq!(hdr.title, "Feature");
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     cell::RefCell,
     io::{ErrorKind, Read, Seek, SeekFrom},
+    ops::Range,
     path::{Component, Components, Path, PathBuf},
     rc::Rc,
 };
@@ -87,6 +88,17 @@ impl IsoFileSystem {
         }
     }
 
+    /// Position of the file data in the image.
+    pub fn get_file_extent<P: AsRef<Path>>(&mut self, path: P) -> Result<Range<u64>> {
+        let record = self.get_dir_entry_from_path(
+            &self.root.clone(),
+            path.as_ref(),
+            &mut path.as_ref().components(),
+        )?;
+        let start = (record.lba as u64) * ISO_SECTOR_LEN;
+        Ok(start..start + record.data_length as u64)
+    }
+
     fn get_children(&mut self, dir: &IsoDirectory) -> Vec<IsoDirectory> {
         let mut children = vec![];
         let start = (dir.lba as u64) * ISO_SECTOR_LEN;
@@ -147,13 +159,9 @@ impl FileSystem for IsoFileSystem {
     }
 
     fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::File> {
-        let record = self.get_dir_entry_from_path(
-            &self.root.clone(),
-            path.as_ref(),
-            &mut path.as_ref().components(),
-        )?;
-        let start = (record.lba as u64) * ISO_SECTOR_LEN;
-        let len = record.data_length as u64;
+        let extent = self.get_file_extent(path.as_ref())?;
+        let start = extent.start;
+        let len = extent.end - extent.start;
         let file = IsoFile {
             start,
             len,
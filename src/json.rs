@@ -19,22 +19,43 @@ pub struct FeatureEntryJson {
     pub id: u16,
     pub title: String,
     pub reels: Vec<ReelEntryJson>,
+    /// Sum of the durations of the reels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct TrailerEntryJson {
     pub id: u16,
     pub title: String,
+    /// H:MM:SS.mmm, like every timecode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct ReelEntryJson {
     pub number: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    /// 35 mm film, FEET+FF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footage: Option<String>,
+    /// Duration in DTS frames, to sum the runtime
+    #[serde(skip)]
+    pub frames: Option<u64>,
 }
 
 pub fn save_json<P: AsRef<Path>>(path: P, entries: Vec<EntryJson>, verbose: bool) -> Result<()> {
-    let data = MetadataJson { data: entries };
-    let json_string = serde_json::to_string_pretty(&data)?;
+    write_json(path, &MetadataJson { data: entries }, verbose)
+}
+
+pub fn write_json<P: AsRef<Path>, T: Serialize>(path: P, data: &T, verbose: bool) -> Result<()> {
+    let json_string = serde_json::to_string_pretty(data)?;
     if verbose {
         println!("JSON output:");
         println!("{}", &json_string);
@@ -4,27 +4,51 @@ use clap::{Args, Parser, Subcommand};
 
 use crate::extract::{Feature, FeatureId, FeatureName, TrailerIds, TrailerNames, Trailers};
 
+mod analyze;
+mod audio;
 mod bcd;
+mod binfile;
 mod cd;
+mod cut;
 mod detect;
+mod dump;
 mod ext234;
 mod ext234file;
 mod extract;
 mod file;
 mod hdd;
 mod hdr;
+mod httpfile;
+mod identify;
 mod info;
+mod ingest;
 mod iso;
 mod isofile;
 mod json;
+#[cfg(test)]
+mod memfile;
+mod merge;
 mod metadata;
 mod osfile;
 mod partitionfile;
+mod platter;
+mod probe;
+mod qcow2file;
+mod repack;
+mod rescue;
+mod rescuefile;
 mod snd;
 mod squash;
 mod squashfsfile;
+mod streamfile;
+mod timecode;
 mod trailers;
+mod unionfile;
 mod utils;
+mod vdisk;
+mod vdiskfile;
+mod vhdfile;
+mod vmdkfile;
 
 #[derive(Parser)]
 #[command(version)]
@@ -73,30 +97,193 @@ enum Commands {
 
         #[arg(long)]
         output_json: Option<PathBuf>,
+
+        /// Merge all inputs into one library
+        #[arg(long)]
+        union: bool,
+
+        /// ddrescue mapfile of the input image, unrecovered areas are reported
+        #[arg(long)]
+        mapfile: Option<PathBuf>,
+    },
+    /// Explain how each input is recognized: candidate formats with their
+    /// confidence, and the stack of containers down to the DTS content
+    #[command(arg_required_else_help = true)]
+    Identify { file: Vec<PathBuf> },
+    /// Show the headers of SND, HDR and R14TRLR.TXT files byte by byte, with
+    /// the field each byte belongs to and its decoded value
+    #[command(arg_required_else_help = true)]
+    Dump {
+        file: Vec<PathBuf>,
+
+        #[arg(long)]
+        output_json: Option<PathBuf>,
+    },
+    /// Statistics of every byte of the SND and HDR headers of a library: its
+    /// values, how they follow the revision, studio and reel, and outliers
+    #[command(arg_required_else_help = true)]
+    AnalyzeHeaders {
+        /// Directories, disc images or drive images, scanned as one library
+        file: Vec<PathBuf>,
+
+        /// Save the statistics of every offset to a CSV file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+
+        /// Only report bytes outside of the known fields
+        #[arg(long)]
+        unknown_only: bool,
     },
     Extract {
         //#[arg(arg_required_else_help = true)]
         input: PathBuf,
         output: PathBuf,
 
+        /// Additional inputs merged with INPUT into one library
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        union_with: Vec<PathBuf>,
+
         #[clap(flatten)]
         feature_group: Option<FeatureGroup>,
 
         #[clap(flatten)]
         trailers_group: Option<TrailersGroup>,
     },
+    /// Combine several damaged rips of the same disc into one image
+    MergeRips {
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+
+        #[arg(long, short)]
+        output: PathBuf,
+
+        /// ddrescue mapfiles of the inputs, in the same order (leave empty for
+        /// rips without mapfile)
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        mapfiles: Vec<PathBuf>,
+
+        /// Mapfile of the merged image [default: OUTPUT.map]
+        #[arg(long)]
+        output_mapfile: Option<PathBuf>,
+    },
+    /// Count the audio frames of SND/AUD/AUE files and report truncation, frame contents are not checked
+    #[command(arg_required_else_help = true)]
+    CheckAudio { file: Vec<PathBuf> },
+    /// Copy the frames of a reel between two offsets into a new reel
+    Cut {
+        input: PathBuf,
+
+        /// Offset of the first frame (H:MM:SS.mmm)
+        #[arg(long)]
+        from: metadata::Offset,
+
+        /// Offset of the end of the cut, excluded (H:MM:SS.mmm)
+        #[arg(long)]
+        to: metadata::Offset,
+
+        /// Reel written
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Join the reels of a feature into one file, for platters, keeping their
+    /// headers in a JSON sidecar
+    Join {
+        #[arg(required = true)]
+        file: Vec<PathBuf>,
+
+        /// Joined file, the sidecar is written next to it with a .json
+        /// extension
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Split a joined file back into the original reels
+    Split {
+        input: PathBuf,
+
+        /// Directory where the reels are written
+        #[arg(long, short)]
+        output: PathBuf,
+
+        /// JSON sidecar written by join [default: INPUT with a .json extension]
+        #[arg(long)]
+        sidecar: Option<PathBuf>,
+    },
+    /// Repack the trailer reel (R14T5.AUD and R14TRLR.TXT) of a directory, ISO
+    /// file or drive image: drop, add and reorder trailers
+    Trailers {
+        input: PathBuf,
+
+        /// Directory where R14T5.AUD and R14TRLR.TXT are written, without it
+        /// the trailers are only listed
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Ids of the trailers of INPUT to leave out
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        drop: Vec<u16>,
+
+        /// Trailers to add after the others: SND files, or trailer reels
+        /// (directory, ISO file, drive image) whose trailers are all added
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        add: Vec<PathBuf>,
+
+        /// Ids of the trailers to put first, in this order
+        #[arg(long, num_args = 1.., value_delimiter = ',')]
+        order: Vec<u16>,
+
+        /// Write each trailer as a SND and HDR file, for XD10 libraries
+        #[arg(long)]
+        split: bool,
+    },
+    /// Convert the feature of a DTS CD into the SND and HDR files of an XD10
+    /// library
+    Ingest {
+        /// Disc tree, ISO file or disc image
+        input: PathBuf,
+
+        /// Directory where the SND and HDR files are written
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Check which DTS files of a rescued disc image are damaged
+    Validate {
+        input: PathBuf,
+
+        /// ddrescue mapfile of the input image
+        #[arg(long)]
+        mapfile: PathBuf,
+    },
 }
 
 fn main() -> ExitCode {
     let args = Cli::parse();
 
     let error = match args.command {
-        Commands::Info { file, output_json } => {
-            info::print_info(&file[..], output_json, args.global_opts.verbose)
+        Commands::Info {
+            file,
+            output_json,
+            union,
+            mapfile,
+        } => info::print_info(
+            &file[..],
+            output_json,
+            union,
+            mapfile,
+            args.global_opts.verbose,
+        ),
+        Commands::Identify { file } => identify::identify_paths(&file, args.global_opts.verbose),
+        Commands::AnalyzeHeaders {
+            file,
+            csv,
+            unknown_only,
+        } => analyze::analyze_headers(&file, csv, unknown_only, args.global_opts.verbose),
+        Commands::Dump { file, output_json } => {
+            dump::dump_headers(&file, output_json, args.global_opts.verbose)
         }
         Commands::Extract {
             input,
             output,
+            union_with,
             feature_group,
             trailers_group,
         } => {
@@ -120,7 +307,63 @@ fn main() -> ExitCode {
                 },
                 None => None,
             };
-            extract::extract_files(input, output, feature, trailers, args.global_opts.verbose)
+            extract::extract_files(
+                input,
+                union_with,
+                output,
+                feature,
+                trailers,
+                args.global_opts.verbose,
+            )
+        }
+        Commands::MergeRips {
+            inputs,
+            output,
+            mapfiles,
+            output_mapfile,
+        } => merge::merge_rips(
+            &inputs,
+            &mapfiles,
+            &output,
+            output_mapfile,
+            args.global_opts.verbose,
+        ),
+        Commands::CheckAudio { file } => audio::check_audio(&file, args.global_opts.verbose),
+        Commands::Cut {
+            input,
+            from,
+            to,
+            output,
+        } => cut::cut_reel(&input, &output, from, to, args.global_opts.verbose),
+        Commands::Join { file, output } => {
+            platter::join_reels(&file, &output, args.global_opts.verbose)
+        }
+        Commands::Split {
+            input,
+            output,
+            sidecar,
+        } => platter::split_reels(&input, &output, sidecar, args.global_opts.verbose),
+        Commands::Trailers {
+            input,
+            output,
+            drop,
+            add,
+            order,
+            split,
+        } => repack::repack_trailers(
+            &input,
+            output.as_deref(),
+            &drop,
+            &add,
+            &order,
+            split,
+            args.global_opts.verbose,
+        ),
+        Commands::Ingest { input, output } => {
+            ingest::ingest_feature(&input, &output, args.global_opts.verbose)
+        }
+        Commands::Validate { input, mapfile } => {
+            rescue::validate_rescued_image(&input, &mapfile, args.global_opts.verbose)
         }
     };
     match error {
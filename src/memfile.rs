@@ -0,0 +1,287 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::file::{DirEntry, File, FileSystem, FileType, WritableFileSystem};
+
+enum MemoryNode {
+    Directory,
+    File(Rc<RefCell<Vec<u8>>>),
+}
+
+pub struct MemoryFileSystem {
+    nodes: BTreeMap<PathBuf, MemoryNode>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), MemoryNode::Directory);
+        MemoryFileSystem { nodes }
+    }
+
+    pub fn insert_file<P: AsRef<Path>>(&mut self, path: P, data: Vec<u8>) -> Result<()> {
+        let mut file = self.create_file(path)?;
+        file.write_all(&data)?;
+        Ok(())
+    }
+
+    fn check_parent_dir(&self, path: &Path) -> Result<()> {
+        match path.parent() {
+            Some(parent) => match self.nodes.get(parent) {
+                Some(MemoryNode::Directory) => Ok(()),
+                Some(MemoryNode::File(_)) => {
+                    Err(anyhow!("Parent of {} is not a directory", path.display()))
+                }
+                None => Err(anyhow!(
+                    "Parent directory of {} does not exist",
+                    path.display()
+                )),
+            },
+            None => Err(anyhow!("Invalid path {}", path.display())),
+        }
+    }
+}
+
+impl Default for MemoryFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    type File = MemoryFile;
+    type DirEntry = MemoryDirEntry;
+
+    fn is_file<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        matches!(
+            self.nodes.get(&normalize_path(path.as_ref())),
+            Some(MemoryNode::File(_))
+        )
+    }
+
+    fn is_dir<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        matches!(
+            self.nodes.get(&normalize_path(path.as_ref())),
+            Some(MemoryNode::Directory)
+        )
+    }
+
+    fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::File> {
+        let path = normalize_path(path.as_ref());
+        match self.nodes.get(&path) {
+            Some(MemoryNode::File(data)) => Ok(MemoryFile {
+                data: data.clone(),
+                current: 0,
+            }),
+            Some(MemoryNode::Directory) => Err(anyhow!("{} is a directory", path.display())),
+            None => Err(anyhow!(
+                "Could not find path ({}) in memory",
+                path.display()
+            )),
+        }
+    }
+
+    fn read_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<Self::DirEntry>> {
+        let path = normalize_path(path.as_ref());
+        match self.nodes.get(&path) {
+            Some(MemoryNode::Directory) => Ok(self
+                .nodes
+                .iter()
+                .filter(|(p, _)| p.parent() == Some(path.as_path()))
+                .map(|(p, n)| MemoryDirEntry {
+                    path: p.clone(),
+                    file_type: match n {
+                        MemoryNode::Directory => FileType::Directory,
+                        MemoryNode::File(_) => FileType::File,
+                    },
+                })
+                .collect()),
+            Some(MemoryNode::File(_)) => Err(anyhow!("{} is not a directory", path.display())),
+            None => Err(anyhow!(
+                "Could not find path ({}) in memory",
+                path.display()
+            )),
+        }
+    }
+}
+
+impl WritableFileSystem for MemoryFileSystem {
+    type Writer = MemoryFile;
+
+    fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = normalize_path(path.as_ref());
+        for ancestor in path.ancestors() {
+            match self.nodes.get(ancestor) {
+                Some(MemoryNode::File(_)) => {
+                    return Err(anyhow!("{} is not a directory", ancestor.display()));
+                }
+                Some(MemoryNode::Directory) => break,
+                None => (),
+            }
+        }
+        for ancestor in path.ancestors() {
+            self.nodes
+                .entry(ancestor.to_path_buf())
+                .or_insert(MemoryNode::Directory);
+        }
+        Ok(())
+    }
+
+    fn create_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::Writer> {
+        let path = normalize_path(path.as_ref());
+        self.check_parent_dir(&path)?;
+        if let Some(MemoryNode::Directory) = self.nodes.get(&path) {
+            return Err(anyhow!("{} is a directory", path.display()));
+        }
+        let data = Rc::new(RefCell::new(vec![]));
+        self.nodes.insert(path, MemoryNode::File(data.clone()));
+        Ok(MemoryFile { data, current: 0 })
+    }
+}
+
+pub struct MemoryFile {
+    data: Rc<RefCell<Vec<u8>>>,
+    current: u64,
+}
+
+impl File for MemoryFile {
+    fn len(&mut self) -> Result<u64> {
+        Ok(self.data.borrow().len() as u64)
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.data.borrow();
+        let len = data.len() as u64;
+        if self.current >= len {
+            return Ok(0);
+        }
+        let start = self.current as usize;
+        let count = buf.len().min(data.len() - start);
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.data.borrow().len() as i64;
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => len + from_end,
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut data = self.data.borrow_mut();
+        let start = self.current as usize;
+        if data.len() < start + buf.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..start + buf.len()].copy_from_slice(buf);
+        self.current += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl From<Vec<u8>> for MemoryFile {
+    fn from(data: Vec<u8>) -> Self {
+        MemoryFile {
+            data: Rc::new(RefCell::new(data)),
+            current: 0,
+        }
+    }
+}
+
+pub struct MemoryDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for MemoryDirEntry {
+    fn path(&self) -> Result<PathBuf> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType> {
+        Ok(self.file_type.clone())
+    }
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => (),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_then_read() {
+        let mut fs = MemoryFileSystem::new();
+        fs.create_dir_all("/out/dts").unwrap();
+        let mut writer = fs.create_file("/out/dts/r1t5.aud").unwrap();
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+
+        assert!(fs.is_dir("/out"));
+        assert!(fs.is_file("out/dts/r1t5.aud"));
+
+        let mut file = fs.open_file("/out/dts/r1t5.aud").unwrap();
+        assert_eq!(file.len().unwrap(), 11);
+        assert_eq!(file.read_exact_bytes_at(5, 6).unwrap(), b"world");
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let mut fs = MemoryFileSystem::new();
+        fs.create_dir_all("/dts").unwrap();
+        fs.insert_file("/dts.exe", vec![0; 4]).unwrap();
+        fs.insert_file("/dts/r1t5.aud", vec![0; 4]).unwrap();
+
+        let entries = fs.read_dir("/").unwrap();
+        let names: Vec<String> = entries.iter().map(|e| e.file_name()).collect();
+        assert_eq!(names, vec!["dts", "dts.exe"]);
+        assert!(entries[0].file_type().unwrap() == FileType::Directory);
+    }
+
+    #[test]
+    fn test_create_file_without_parent() {
+        let mut fs = MemoryFileSystem::new();
+        assert!(fs.create_file("/missing/r14t5.aud").is_err());
+    }
+}
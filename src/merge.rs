@@ -0,0 +1,272 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    file::{File, FileSystem, WritableFileSystem},
+    osfile::{OsFile, OsFileSystem, is_device_path},
+    rescue::{analyze_rescued_iso, open_rescued_image, print_reels_check, print_rescue_report},
+    rescuefile::{
+        RescueBlock, RescueMap, RescueStatus, decode_rescue_map_from_file,
+        encode_rescue_map_to_file,
+    },
+};
+
+const SECTOR_LEN: u64 = 2048;
+const SECTORS_PER_CHUNK: u64 = 256;
+
+struct Rip {
+    path: PathBuf,
+    file: Box<dyn File>,
+    len: u64,
+    map: Option<RescueMap>,
+}
+
+#[derive(Default)]
+struct MergeStats {
+    sectors: u64,
+    missing: u64,
+    disagreements: u64,
+    used: Vec<u64>,
+}
+
+/// Combines several rips of the same disc sector by sector. For each sector,
+/// only the rips whose mapfile marks it as recovered are considered (rips
+/// without a mapfile always are), and the content found in most of them wins,
+/// rips with a mapfile counting twice.
+pub fn merge_rips(
+    inputs: &[PathBuf],
+    mapfiles: &[PathBuf],
+    output: &Path,
+    output_mapfile: Option<PathBuf>,
+    verbose: bool,
+) -> Result<()> {
+    if inputs.len() < 2 {
+        return Err(anyhow!("At least two rips are needed"));
+    }
+    if mapfiles.len() > inputs.len() {
+        return Err(anyhow!("More mapfiles than rips"));
+    }
+    let output_mapfile = output_mapfile.unwrap_or(get_default_mapfile_path(output));
+
+    let mut rips = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, p)| open_rip(p, mapfiles.get(i).filter(|m| !m.as_os_str().is_empty())))
+        .collect::<Result<Vec<Rip>>>()?;
+
+    println!("Merging {} rips:", rips.len());
+    for r in &rips {
+        println!(
+            "  {}: {} bytes{}",
+            r.path.display(),
+            r.len,
+            if r.map.is_some() {
+                ", with mapfile"
+            } else {
+                ""
+            }
+        );
+    }
+
+    let len = rips.iter().map(|r| r.len).max().unwrap_or(0);
+    let mut output_fs = OsFileSystem;
+    let mut output_file = output_fs.create_file(output)?;
+    let mut stats = MergeStats {
+        used: vec![0; rips.len()],
+        ..Default::default()
+    };
+    let mut blocks: Vec<RescueBlock> = vec![];
+
+    let mut position = 0;
+    while position < len {
+        let chunk_len = (SECTOR_LEN * SECTORS_PER_CHUNK).min(len - position);
+        let chunks = rips
+            .iter_mut()
+            .map(|r| read_chunk(r, position, chunk_len))
+            .collect::<Result<Vec<Vec<u8>>>>()?;
+        let mut merged = vec![0; chunk_len as usize];
+        for start in (0..chunk_len).step_by(SECTOR_LEN as usize) {
+            let end = (start + SECTOR_LEN).min(chunk_len);
+            let sector = position + start..position + end;
+            let status = match merge_sector(&rips, &chunks, sector.clone(), &mut stats, verbose) {
+                Some(i) => {
+                    merged[start as usize..end as usize]
+                        .copy_from_slice(&chunks[i][start as usize..end as usize]);
+                    RescueStatus::Finished
+                }
+                None => {
+                    stats.missing += 1;
+                    RescueStatus::BadSector
+                }
+            };
+            push_block(&mut blocks, sector.start, sector.end - sector.start, status);
+        }
+        output_file.write_all(&merged)?;
+        position += chunk_len;
+    }
+    output_file.flush()?;
+
+    let mut map_file = output_fs.create_file(&output_mapfile)?;
+    encode_rescue_map_to_file(&mut map_file, &RescueMap::from_blocks(blocks))?;
+    map_file.flush()?;
+
+    println!(
+        "Merged {} sectors, {} still missing, {} with disagreeing copies",
+        stats.sectors, stats.missing, stats.disagreements
+    );
+    for (r, used) in rips.iter().zip(&stats.used) {
+        println!("  {} sectors taken from {}", used, r.path.display());
+    }
+    println!("Created {:?}", output);
+    println!("Created {:?}", &output_mapfile);
+    println!();
+
+    let report = analyze_rescued_iso(open_rescued_image(output, &output_mapfile)?, verbose)?;
+    print_rescue_report(&report);
+    print_reels_check(output, &output_mapfile, &report)
+}
+
+/// Returns the index of the rip the sector is taken from.
+fn merge_sector(
+    rips: &[Rip],
+    chunks: &[Vec<u8>],
+    sector: std::ops::Range<u64>,
+    stats: &mut MergeStats,
+    verbose: bool,
+) -> Option<usize> {
+    stats.sectors += 1;
+    let offset = sector.start % (SECTOR_LEN * SECTORS_PER_CHUNK);
+    let local = offset as usize..(offset + sector.end - sector.start) as usize;
+
+    // (rip used as reference for the content, votes)
+    let mut candidates: Vec<(usize, u32)> = vec![];
+    for (i, r) in rips.iter().enumerate() {
+        if sector.end > r.len {
+            continue;
+        }
+        let weight = match &r.map {
+            Some(m) if m.bad_ranges_in(sector.clone()).is_empty() => 2,
+            Some(_) => continue,
+            None => 1,
+        };
+        match candidates
+            .iter_mut()
+            .find(|(c, _)| chunks[*c][local.clone()] == chunks[i][local.clone()])
+        {
+            Some((_, votes)) => *votes += weight,
+            None => candidates.push((i, weight)),
+        }
+    }
+    if candidates.len() > 1 {
+        stats.disagreements += 1;
+        if verbose {
+            println!(
+                "  sector {:#x}: {} different contents",
+                sector.start,
+                candidates.len()
+            );
+        }
+    }
+    // The first rip wins ties
+    let best = candidates
+        .iter()
+        .rev()
+        .max_by_key(|(_, votes)| *votes)
+        .map(|(i, _)| *i)?;
+    stats.used[best] += 1;
+    Some(best)
+}
+
+fn read_chunk(rip: &mut Rip, position: u64, len: u64) -> Result<Vec<u8>> {
+    let available = rip.len.saturating_sub(position).min(len);
+    let mut chunk = vec![0; len as usize];
+    if available > 0 {
+        let bytes = rip.file.read_exact_bytes_at(available as usize, position)?;
+        chunk[..available as usize].copy_from_slice(&bytes);
+    }
+    Ok(chunk)
+}
+
+fn push_block(blocks: &mut Vec<RescueBlock>, start: u64, len: u64, status: RescueStatus) {
+    match blocks.last_mut() {
+        Some(last) if last.status == status && last.start + last.len == start => last.len += len,
+        _ => blocks.push(RescueBlock { start, len, status }),
+    }
+}
+
+fn open_rip(path: &Path, mapfile: Option<&PathBuf>) -> Result<Rip> {
+    let mut os_fs = OsFileSystem;
+    let mut file: Box<dyn File> = if os_fs.is_file(path) {
+        Box::new(os_fs.open_file(path)?)
+    } else if is_device_path(path) {
+        Box::new(OsFile::open_device(path)?)
+    } else {
+        return Err(anyhow!("Could not open input {}", path.display()));
+    };
+    let map = match mapfile {
+        Some(m) => Some(decode_rescue_map_from_file(&mut os_fs.open_file(m)?, m)?),
+        None => None,
+    };
+    Ok(Rip {
+        path: path.to_path_buf(),
+        len: file.len()?,
+        file,
+        map,
+    })
+}
+
+fn get_default_mapfile_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".map");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memfile::MemoryFile;
+
+    fn get_rip(data: Vec<u8>, map: Option<RescueMap>) -> Rip {
+        Rip {
+            path: PathBuf::from("rip"),
+            len: data.len() as u64,
+            file: Box::new(MemoryFile::from(data)),
+            map,
+        }
+    }
+
+    #[test]
+    fn test_merge_sector_votes() {
+        let good = vec![0x11; SECTOR_LEN as usize];
+        let bad = vec![0x00; SECTOR_LEN as usize];
+        let unrecovered = RescueMap::from_blocks(vec![RescueBlock {
+            start: 0,
+            len: SECTOR_LEN,
+            status: RescueStatus::BadSector,
+        }]);
+        let rips = vec![
+            get_rip(bad.clone(), Some(unrecovered)),
+            get_rip(bad.clone(), None),
+            get_rip(good.clone(), None),
+            get_rip(good.clone(), None),
+        ];
+        let chunks = vec![bad.clone(), bad, good.clone(), good];
+        let mut stats = MergeStats {
+            used: vec![0; rips.len()],
+            ..Default::default()
+        };
+
+        // The unrecovered sector of the first rip is ignored, two copies agree
+        let best = merge_sector(&rips, &chunks, 0..SECTOR_LEN, &mut stats, false);
+        assert_eq!(best, Some(2));
+        assert_eq!(stats.disagreements, 1);
+        assert_eq!(
+            merge_sector(&rips[..1], &chunks, 0..SECTOR_LEN, &mut stats, false),
+            None
+        );
+    }
+}
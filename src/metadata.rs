@@ -1,8 +1,11 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
 
 use crate::{
     json::{EntryJson, FeatureEntryJson, ReelEntryJson, TrailerEntryJson},
-    snd::SND_HEADER_LEN_WITH_ENCRYPTION,
+    snd::{SND_HEADER_LEN_WITH_ENCRYPTION, SND_TRAILERS_REEL},
+    timecode::{FrameRate, Timecode},
 };
 
 enum _Type {
@@ -10,6 +13,15 @@ enum _Type {
     Individual,
 }
 
+/// DTS frames last 1/30 s, whatever the rate of the film
+pub const DTS_FRAME_RATE: FrameRate = FrameRate::Fps30;
+pub const OFFSET_FRAMES_PER_SECOND: u64 = DTS_FRAME_RATE.nominal();
+/// Last offset the header can hold, 99:59:59 and 29 frames
+const OFFSET_MAX_FRAMES: u64 = 100 * 3600 * OFFSET_FRAMES_PER_SECOND - 1;
+
+/// Timecode of the header offsets and of the frames, as stored: one BCD byte
+/// per field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Offset {
     pub frames: u8,
     pub seconds: u8,
@@ -17,38 +29,79 @@ pub struct Offset {
     pub hours: u8,
 }
 
+impl Offset {
+    /// Counts past the last offset, 99:59:59 and 29 frames, are clamped to it
+    pub fn from_frames(count: u64) -> Self {
+        let count = count.min(OFFSET_MAX_FRAMES);
+        let seconds = count / OFFSET_FRAMES_PER_SECOND;
+        Offset {
+            frames: (count % OFFSET_FRAMES_PER_SECOND) as u8,
+            seconds: (seconds % 60) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            hours: (seconds / 3600) as u8,
+        }
+    }
+
+    pub fn to_frames(self) -> u64 {
+        let seconds = self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64;
+        seconds * OFFSET_FRAMES_PER_SECOND + self.frames as u64
+    }
+
+    pub fn to_timecode(self) -> Timecode {
+        Timecode::new(self.to_frames(), DTS_FRAME_RATE)
+    }
+
+    /// To the nearest DTS frame
+    pub fn from_timecode(timecode: Timecode) -> Self {
+        Offset::from_frames(timecode.to_rate(DTS_FRAME_RATE).count())
+    }
+}
+
+/// "H:MM:SS.mmm"
 impl fmt::Display for Offset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}:{:02}:{:02}.{:03}",
-            self.hours,
-            self.minutes,
-            self.seconds,
-            (self.frames as u64 * 1000) / 30
-        )
+        write!(f, "{}", self.to_timecode().to_clock_string())
+    }
+}
+
+/// Parses offsets as displayed, "H:MM:SS.mmm", or anything accepted by
+/// `Timecode`, like "HH:MM:SS:FF@24" or 35 mm footage "FEET+FF".
+impl FromStr for Offset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let timecode: Timecode = s.parse()?;
+        if timecode.to_rate(DTS_FRAME_RATE).count() > OFFSET_MAX_FRAMES {
+            return Err(anyhow!("Invalid offset '{}', offsets stop at 99 hours", s));
+        }
+        Ok(Offset::from_timecode(timecode))
     }
 }
 
+#[derive(PartialEq)]
 pub enum Revision {
     H1,
     XD,
     XDA,
 }
 
+/// Bytes every XDA header has, around and between its fields
+const XDA_MARKERS: [(usize, u8); 9] = [
+    (18, b' '),
+    (31, b' '),
+    (47, b' '),
+    (50, b'D'),
+    (51, b' '),
+    (55, b' '),
+    (59, b' '),
+    (65, 0),
+    (66, 0),
+];
+
 impl Revision {
     pub fn from_header(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> Revision {
         if bytes[60] == b'*' {
-            if bytes[18] == b' '
-                && bytes[31] == b' '
-                && bytes[47] == b' '
-                && bytes[50] == b'D'
-                && bytes[51] == b' '
-                && bytes[55] == b' '
-                && bytes[59] == b' '
-                && bytes[65] == 0
-                && bytes[66] == 0
-            {
+            if Revision::count_xda_markers(bytes) == XDA_MARKERS.len() {
                 Revision::XDA
             } else {
                 Revision::XD
@@ -57,6 +110,20 @@ impl Revision {
             Revision::H1
         }
     }
+
+    /// Whether a header taken as XD has nearly all the XDA markers, an
+    /// unexpected layout: its XDA fields are then left out.
+    pub fn has_xda_markers(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> bool {
+        let count = Revision::count_xda_markers(bytes);
+        count >= XDA_MARKERS.len() - 2 && count < XDA_MARKERS.len()
+    }
+
+    fn count_xda_markers(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> usize {
+        XDA_MARKERS
+            .iter()
+            .filter(|(i, value)| bytes[*i] == *value)
+            .count()
+    }
 }
 
 impl fmt::Display for Revision {
@@ -73,14 +140,15 @@ impl fmt::Display for Revision {
     }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum BackupSoundtrackFormat {
-    DolbyA = 0x00,
-    DolbySR = 0x01,
-    Academy = 0x02,
-    NonSync = 0x80,
-    LastReelDolbySR = 0x81,
+    DolbyA,
+    DolbySR,
+    Academy,
+    NonSync,
+    LastReelDolbySR,
+    /// Value of a header not matching any known format
+    Unknown(u8),
 }
 
 impl fmt::Display for BackupSoundtrackFormat {
@@ -91,6 +159,7 @@ impl fmt::Display for BackupSoundtrackFormat {
             BackupSoundtrackFormat::Academy => write!(f, "Academy"),
             BackupSoundtrackFormat::NonSync => write!(f, "Non-Sync"),
             BackupSoundtrackFormat::LastReelDolbySR => write!(f, "Last reel - Dolby SR"),
+            BackupSoundtrackFormat::Unknown(value) => write!(f, "Unknown ({:#04x})", value),
         }
     }
 }
@@ -114,16 +183,25 @@ pub struct HdrFileMetadata {
 
 impl HdrFileMetadata {
     pub fn to_entry(&self) -> EntryJson {
-        if self.reel == 14 {
+        if self.reel == SND_TRAILERS_REEL {
             EntryJson::Trailer(TrailerEntryJson {
                 id: self.id,
                 title: self.title.clone(),
+                duration: None,
             })
         } else {
             EntryJson::Feature(FeatureEntryJson {
                 id: self.id,
                 title: self.title.clone(),
-                reels: vec![ReelEntryJson { number: self.reel }],
+                reels: vec![ReelEntryJson {
+                    number: self.reel,
+                    start: None,
+                    end: None,
+                    duration: None,
+                    footage: None,
+                    frames: None,
+                }],
+                runtime: None,
             })
         }
     }
@@ -147,6 +225,43 @@ pub struct SndFileMetadata {
     pub end_offset: Option<Offset>,
     pub encryption_key: Option<u16>,
     pub xd: Option<XDMetadata>,
+    /// Header the metadata was decoded from, None for new headers
+    pub raw_header: Option<Vec<u8>>,
+    /// Values of the header that could not be decoded
+    pub warnings: Vec<String>,
+}
+
+impl SndFileMetadata {
+    /// Duration between the start and end offsets, None without an end
+    pub fn duration(&self) -> Option<Timecode> {
+        let start = self.start_offset.map_or(0, |o| o.to_frames());
+        self.end_offset
+            .map(|end| Timecode::new(end.to_frames().saturating_sub(start), DTS_FRAME_RATE))
+    }
+
+    /// `length` is the duration of the reel, from its offsets or its audio
+    pub fn to_entry(&self, length: Option<Timecode>) -> EntryJson {
+        match self.snd_type {
+            SndType::Trailer => EntryJson::Trailer(TrailerEntryJson {
+                id: self.id,
+                title: self.title.clone(),
+                duration: length.map(|d| d.to_clock_string()),
+            }),
+            SndType::Feature => EntryJson::Feature(FeatureEntryJson {
+                id: self.id,
+                title: self.title.clone(),
+                reels: vec![ReelEntryJson {
+                    number: self.reel,
+                    start: self.start_offset.map(|o| o.to_string()),
+                    end: self.end_offset.map(|o| o.to_string()),
+                    duration: length.map(|d| d.to_clock_string()),
+                    footage: length.map(|d| d.to_feet_frames().to_string()),
+                    frames: length.map(|d| d.to_rate(DTS_FRAME_RATE).count()),
+                }],
+                runtime: None,
+            }),
+        }
+    }
 }
 
 pub struct XDMetadata {
@@ -171,6 +286,49 @@ pub struct TrailersMetadataTxtEntry {
     pub end: usize,
     pub offset: usize,
 }
+
+/// START and END count frames of the 5 tracks of trailers
+impl TrailersMetadataTxtEntry {
+    pub fn start_timecode(&self) -> Timecode {
+        Timecode::new(self.start as u64, DTS_FRAME_RATE)
+    }
+
+    pub fn end_timecode(&self) -> Timecode {
+        Timecode::new(self.end as u64, DTS_FRAME_RATE)
+    }
+
+    pub fn duration(&self) -> Timecode {
+        self.end_timecode() - self.start_timecode()
+    }
+
+    pub fn to_entry(&self) -> EntryJson {
+        EntryJson::Trailer(TrailerEntryJson {
+            id: self.id,
+            title: self.title.clone(),
+            duration: Some(self.duration().to_clock_string()),
+        })
+    }
+}
+
 pub struct TrailersMetadata {
     pub entries: Vec<TrailersMetadataTxtEntry>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset() {
+        let offset = Offset::from_frames(3 * 3600 * 30 + 2);
+        assert_eq!(offset.hours, 3);
+        assert_eq!(offset.to_string(), "3:00:00.066");
+        assert_eq!("3:00:00.066".parse::<Offset>().unwrap(), offset);
+
+        // Past 99 hours, the whole offset is clamped
+        let last = Offset::from_frames(OFFSET_MAX_FRAMES);
+        assert_eq!(Offset::from_frames(100 * 3600 * 30 + 5), last);
+        assert_eq!(last.to_string(), "99:59:59.966");
+        assert!("100:00:00".parse::<Offset>().is_err());
+    }
+}
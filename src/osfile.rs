@@ -1,12 +1,12 @@
 use std::{
     fs,
-    io::{Read, Seek},
+    io::{Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
-use crate::file::{DirEntry, File, FileSystem, FileType};
+use crate::file::{DirEntry, File, FileSystem, FileType, WritableFileSystem};
 
 pub struct OsFileSystem;
 
@@ -32,12 +32,107 @@ impl FileSystem for OsFileSystem {
     }
 }
 
+impl WritableFileSystem for OsFileSystem {
+    type Writer = OsFile;
+
+    fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    fn create_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::Writer> {
+        Ok(OsFile::from(fs::File::create(path)?))
+    }
+}
+
+/// Returns true for block devices (CD drives, disks, loop devices). Character
+/// devices are left out, their size can not be queried.
+pub fn is_device_path(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        fs::metadata(path).is_ok_and(|m| m.file_type().is_block_device())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
 pub struct OsFile {
     file: fs::File,
+    device_len: Option<u64>,
+}
+
+impl OsFile {
+    /// Opens a device read-only. Its size is queried from the device, seeking
+    /// to the end is not reliable for all of them.
+    pub fn open_device<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = fs::OpenOptions::new().read(true).open(path.as_ref())?;
+        let device_len = get_device_len(&file).map_err(|e| {
+            anyhow!(
+                "Could not get size of device {}: {}",
+                path.as_ref().display(),
+                e
+            )
+        })?;
+        Ok(OsFile {
+            file,
+            device_len: Some(device_len),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_device_len(file: &fs::File) -> std::io::Result<u64> {
+    use std::os::fd::AsRawFd;
+
+    const BLKGETSIZE64: libc::Ioctl = libc::_IOR::<libc::size_t>(0x12, 114);
+
+    let mut len: u64 = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64, &mut len) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(len)
+}
+
+#[cfg(target_os = "macos")]
+fn get_device_len(file: &fs::File) -> std::io::Result<u64> {
+    use std::os::fd::AsRawFd;
+
+    const DKIOCGETBLOCKSIZE: libc::c_ulong = 0x4004_6418;
+    const DKIOCGETBLOCKCOUNT: libc::c_ulong = 0x4008_6419;
+
+    let mut block_size: u32 = 0;
+    let mut block_count: u64 = 0;
+    unsafe {
+        if libc::ioctl(file.as_raw_fd(), DKIOCGETBLOCKSIZE, &mut block_size) != 0
+            || libc::ioctl(file.as_raw_fd(), DKIOCGETBLOCKCOUNT, &mut block_count) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(block_size as u64 * block_count)
+}
+
+/// Without a size query, seeking to the end, which gives 0 for devices that do
+/// not support it.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn get_device_len(mut file: &fs::File) -> std::io::Result<u64> {
+    let len = file.seek(std::io::SeekFrom::End(0))?;
+    file.seek(std::io::SeekFrom::Start(0))?;
+    if len == 0 {
+        return Err(std::io::Error::other("size unknown"));
+    }
+    Ok(len)
 }
 
 impl File for OsFile {
     fn len(&mut self) -> Result<u64> {
+        if let Some(len) = self.device_len {
+            return Ok(len);
+        }
         let position = self.stream_position()?;
         let len = self.seek(std::io::SeekFrom::End(0))?;
         if position != len {
@@ -59,9 +154,22 @@ impl Seek for OsFile {
     }
 }
 
+impl Write for OsFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
 impl From<fs::File> for OsFile {
     fn from(file: fs::File) -> Self {
-        OsFile { file }
+        OsFile {
+            file,
+            device_len: None,
+        }
     }
 }
 
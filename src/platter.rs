@@ -0,0 +1,444 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::AudioLayout,
+    file::{File, WritableFileSystem},
+    info::open_input_file,
+    metadata::SndFileMetadata,
+    osfile::OsFileSystem,
+    snd::{decode_snd_header_from_file, set_header_offsets},
+};
+
+/// Bytes copied at once
+const COPY_LEN: u64 = 1 << 20;
+
+/// Sidecar of a joined file: the reels in the order of their audio
+#[derive(Serialize, Deserialize)]
+struct PlatterJson {
+    reels: Vec<PlatterReelJson>,
+}
+
+/// The header is kept as raw bytes, the decoded fields are only there to be
+/// read by people.
+#[derive(Serialize, Deserialize)]
+struct PlatterReelJson {
+    file: String,
+    reel: u8,
+    id: u16,
+    title: String,
+    tracks: u8,
+    start_offset: Option<String>,
+    end_offset: Option<String>,
+    encrypted: bool,
+    /// Hexadecimal
+    header: String,
+    /// Bytes after the header, an incomplete last frame included
+    audio_len: u64,
+    /// Of the whole reel file
+    md5: String,
+}
+
+struct ReelInput {
+    path: PathBuf,
+    file: Box<dyn File>,
+    metadata: SndFileMetadata,
+    layout: AudioLayout,
+    header: Vec<u8>,
+    len: u64,
+}
+
+/// Joins the reels of a feature into one file, in reel order: the header of
+/// the first reel, without start and end offsets, then the audio of every
+/// reel. The headers are kept in a JSON sidecar next to the output.
+pub fn join_reels(inputs: &[PathBuf], output: &Path, verbose: bool) -> Result<()> {
+    let mut reels = vec![];
+    for path in inputs {
+        reels.push(ReelInput::open(path, open_input_file(path)?)?);
+    }
+
+    let mut output_fs = OsFileSystem;
+    let mut output_file = output_fs.create_file(output)?;
+    let sidecar = join_files(reels, &mut output_file, verbose)?;
+    output_file.flush()?;
+    println!("Created {:?} from {} reels", output, sidecar.reels.len());
+
+    let sidecar_path = get_sidecar_path(output);
+    let mut sidecar_file = output_fs.create_file(&sidecar_path)?;
+    sidecar_file.write_all(serde_json::to_string_pretty(&sidecar)?.as_bytes())?;
+    sidecar_file.flush()?;
+    println!("Created {:?}", sidecar_path);
+    Ok(())
+}
+
+impl ReelInput {
+    fn open(path: &Path, mut file: Box<dyn File>) -> Result<Self> {
+        let metadata = decode_snd_header_from_file(file.as_mut(), path)?;
+        let layout = AudioLayout::from_file(file.as_mut())?;
+        let header = file.read_exact_bytes_at(layout.header_len as usize, 0)?;
+        let len = file.len()?;
+        Ok(ReelInput {
+            path: path.to_path_buf(),
+            file,
+            metadata,
+            layout,
+            header,
+            len,
+        })
+    }
+
+    /// Name of the reel in the sidecar, the reel is written back with it
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or(format!("r{}t5.aud", self.metadata.reel))
+    }
+}
+
+/// Writes the joined file and returns its sidecar
+fn join_files(
+    mut reels: Vec<ReelInput>,
+    output_file: &mut dyn Write,
+    verbose: bool,
+) -> Result<PlatterJson> {
+    reels.sort_by_key(|r| r.metadata.reel);
+    check_reels(&reels)?;
+
+    let mut header = reels[0].header.clone();
+    set_header_offsets(&mut header, &None, &None)?;
+    output_file.write_all(&header)?;
+
+    let mut sidecar = PlatterJson { reels: vec![] };
+    for reel in &mut reels {
+        let mut md5 = md5::Context::new();
+        md5.consume(&reel.header);
+        let audio_len = reel.len - reel.layout.header_len;
+        copy_bytes(
+            reel.file.as_mut(),
+            reel.layout.header_len,
+            audio_len,
+            output_file,
+            &mut md5,
+        )?;
+        if verbose {
+            println!(
+                "join_reels: reel {} from {:?}, {} audio bytes",
+                reel.metadata.reel, reel.path, audio_len
+            );
+        }
+        sidecar.reels.push(PlatterReelJson {
+            file: reel.file_name(),
+            reel: reel.metadata.reel,
+            id: reel.metadata.id,
+            title: reel.metadata.title.clone(),
+            tracks: reel.metadata.tracks,
+            start_offset: reel.metadata.start_offset.map(|o| o.to_string()),
+            end_offset: reel.metadata.end_offset.map(|o| o.to_string()),
+            encrypted: reel.layout.encrypted,
+            header: to_hex(&reel.header),
+            audio_len,
+            md5: format!("{:x}", md5.finalize()),
+        });
+    }
+    Ok(sidecar)
+}
+
+/// The joined audio only makes sense with the same layout in every reel
+fn check_reels(reels: &[ReelInput]) -> Result<()> {
+    let Some(first) = reels.first() else {
+        return Err(anyhow!("No reel to join"));
+    };
+    for pair in reels.windows(2) {
+        if pair[0].metadata.reel == pair[1].metadata.reel {
+            return Err(anyhow!(
+                "Reel {} found more than once: {:?} and {:?}",
+                pair[0].metadata.reel,
+                pair[0].path,
+                pair[1].path
+            ));
+        }
+    }
+    // Split writes every reel back under its name
+    for (i, reel) in reels.iter().enumerate() {
+        if let Some(other) = reels[i + 1..]
+            .iter()
+            .find(|r| r.file_name().eq_ignore_ascii_case(&reel.file_name()))
+        {
+            return Err(anyhow!(
+                "Reels {} and {} have the same file name: {:?} and {:?}",
+                reel.metadata.reel,
+                other.metadata.reel,
+                reel.path,
+                other.path
+            ));
+        }
+    }
+    let missing: Vec<String> = (1..reels.last().map_or(1, |r| r.metadata.reel))
+        .filter(|n| !reels.iter().any(|r| r.metadata.reel == *n))
+        .map(|n| n.to_string())
+        .collect();
+    if !missing.is_empty() {
+        println!(
+            "Warning: Seems like some reels are missing ({})",
+            missing.join(", ")
+        );
+    }
+    for reel in reels {
+        if reel.layout.tracks != first.layout.tracks
+            || reel.layout.encrypted != first.layout.encrypted
+        {
+            return Err(anyhow!(
+                "Reels {} and {} have different audio layouts ({} and {} tracks{})",
+                first.metadata.reel,
+                reel.metadata.reel,
+                first.layout.tracks,
+                reel.layout.tracks,
+                if reel.layout.encrypted != first.layout.encrypted {
+                    ", encrypted and not"
+                } else {
+                    ""
+                }
+            ));
+        }
+        // The joined file keeps the key of the first reel only
+        if reel.metadata.encryption_key != first.metadata.encryption_key {
+            return Err(anyhow!(
+                "Reels {} and {} are encrypted with different keys",
+                first.metadata.reel,
+                reel.metadata.reel
+            ));
+        }
+        if reel.metadata.id != first.metadata.id {
+            println!(
+                "Warning: reel {} has id {}, reel {} has id {}",
+                reel.metadata.reel, reel.metadata.id, first.metadata.reel, first.metadata.id
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Splits a joined file back into its reels, with their original headers
+/// from the sidecar. The reels are checked against their original MD5.
+pub fn split_reels(
+    input: &Path,
+    output: &Path,
+    sidecar: Option<PathBuf>,
+    verbose: bool,
+) -> Result<()> {
+    let sidecar_path = sidecar.unwrap_or(get_sidecar_path(input));
+    let sidecar: PlatterJson = serde_json::from_str(&std::fs::read_to_string(&sidecar_path)?)
+        .map_err(|e| anyhow!("Could not read {}: {}", sidecar_path.display(), e))?;
+
+    let mut file = open_input_file(input)?;
+    let mut output_fs = OsFileSystem;
+    split_file(
+        file.as_mut(),
+        input,
+        &sidecar,
+        &sidecar_path,
+        &mut output_fs,
+        output,
+        verbose,
+    )
+}
+
+/// Writes the reels of a joined file into `output`
+fn split_file<W: WritableFileSystem>(
+    file: &mut dyn File,
+    input: &Path,
+    sidecar: &PlatterJson,
+    sidecar_path: &Path,
+    output_fs: &mut W,
+    output: &Path,
+    verbose: bool,
+) -> Result<()> {
+    let Some(first) = sidecar.reels.first() else {
+        return Err(anyhow!("No reel in {}", sidecar_path.display()));
+    };
+    let joined_header_len = from_hex(&first.header)?.len() as u64;
+    let expected_len = joined_header_len + sidecar.reels.iter().map(|r| r.audio_len).sum::<u64>();
+    let len = file.len()?;
+    if len != expected_len {
+        return Err(anyhow!(
+            "{} is {} bytes long, {} bytes expected from {}",
+            input.display(),
+            len,
+            expected_len,
+            sidecar_path.display()
+        ));
+    }
+
+    output_fs.create_dir_all(output)?;
+    let mut offset = joined_header_len;
+    let mut mismatches = 0;
+    for reel in &sidecar.reels {
+        let name = Path::new(&reel.file)
+            .file_name()
+            .ok_or(anyhow!("Invalid reel file name '{}'", reel.file))?;
+        let path = output.join(name);
+        let header = from_hex(&reel.header)?;
+        let mut md5 = md5::Context::new();
+        md5.consume(&header);
+        let mut reel_file = output_fs.create_file(&path)?;
+        reel_file.write_all(&header)?;
+        copy_bytes(file, offset, reel.audio_len, &mut reel_file, &mut md5)?;
+        reel_file.flush()?;
+        offset += reel.audio_len;
+
+        let digest = format!("{:x}", md5.finalize());
+        if verbose {
+            println!("split_reels: reel {}, md5 {}", reel.reel, digest);
+        }
+        if digest == reel.md5 {
+            println!("Created {:?} (reel {})", path, reel.reel);
+        } else {
+            println!(
+                "Created {:?} (reel {}), but it differs from the original: md5 {} instead of {}",
+                path, reel.reel, digest, reel.md5
+            );
+            mismatches += 1;
+        }
+    }
+    if mismatches > 0 {
+        return Err(anyhow!("{} reels differ from the original", mismatches));
+    }
+    Ok(())
+}
+
+pub fn copy_bytes(
+    file: &mut dyn File,
+    offset: u64,
+    len: u64,
+    output: &mut dyn Write,
+    md5: &mut md5::Context,
+) -> Result<()> {
+    let mut copied = 0;
+    while copied < len {
+        let count = COPY_LEN.min(len - copied);
+        let bytes = file.read_exact_bytes_at(count as usize, offset + copied)?;
+        md5.consume(&bytes);
+        output.write_all(&bytes)?;
+        copied += count;
+    }
+    Ok(())
+}
+
+fn get_sidecar_path(joined: &Path) -> PathBuf {
+    joined.with_extension("json")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(anyhow!("Invalid hexadecimal header '{}'", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| anyhow!("Invalid hexadecimal header '{}'", hex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        file::FileSystem,
+        memfile::{MemoryFile, MemoryFileSystem},
+        metadata::Offset,
+        snd::{get_test_header, get_test_snd},
+    };
+
+    fn get_reel(reel: u8, frames: usize) -> Vec<u8> {
+        let mut metadata = get_test_header(1234, reel);
+        metadata.start_offset = Some(Offset::from_frames(reel as u64 * 10));
+        metadata.end_offset = Some(Offset::from_frames(reel as u64 * 100));
+        get_test_snd(&metadata, frames)
+    }
+
+    fn get_input(path: &str, data: &[u8]) -> ReelInput {
+        ReelInput::open(Path::new(path), Box::new(MemoryFile::from(data.to_vec()))).unwrap()
+    }
+
+    #[test]
+    fn test_join_split_round_trip() {
+        let mut truncated = get_reel(2, 2);
+        truncated.extend([0xFF; 100]);
+        let reels = [
+            ("r1t5.aud", get_reel(1, 3)),
+            ("r2t5.aud", truncated),
+            ("r3t5.aud", get_reel(3, 1)),
+        ];
+        let inputs = vec![
+            get_input("/in/r3t5.aud", &reels[2].1),
+            get_input("/in/r1t5.aud", &reels[0].1),
+            get_input("/in/r2t5.aud", &reels[1].1),
+        ];
+        let mut joined = vec![];
+        let sidecar = join_files(inputs, &mut joined, false).unwrap();
+        assert_eq!(
+            sidecar.reels.iter().map(|r| r.reel).collect::<Vec<u8>>(),
+            vec![1, 2, 3]
+        );
+
+        let mut output_fs = MemoryFileSystem::new();
+        split_file(
+            &mut MemoryFile::from(joined),
+            Path::new("feature.aud"),
+            &sidecar,
+            Path::new("feature.json"),
+            &mut output_fs,
+            Path::new("/out"),
+            false,
+        )
+        .unwrap();
+        for (name, data) in &reels {
+            let mut file = output_fs.open_file(Path::new("/out").join(name)).unwrap();
+            let mut split = vec![];
+            file.read_to_end(&mut split).unwrap();
+            assert!(split == *data, "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn test_join_duplicate_names() {
+        let inputs = vec![
+            get_input("/a/reel.aud", &get_reel(1, 1)),
+            get_input("/b/reel.aud", &get_reel(2, 1)),
+        ];
+        assert!(join_files(inputs, &mut vec![], false).is_err());
+    }
+
+    #[test]
+    fn test_join_different_keys() {
+        let get_encrypted_reel = |reel, key| {
+            let mut metadata = get_test_header(1234, reel);
+            metadata.encryption_key = Some(key);
+            get_test_snd(&metadata, 1)
+        };
+        let inputs = vec![
+            get_input("/in/r1t5.aue", &get_encrypted_reel(1, 0x1234)),
+            get_input("/in/r2t5.aue", &get_encrypted_reel(2, 0x1234)),
+        ];
+        assert!(join_files(inputs, &mut vec![], false).is_ok());
+        let inputs = vec![
+            get_input("/in/r1t5.aue", &get_encrypted_reel(1, 0x1234)),
+            get_input("/in/r2t5.aue", &get_encrypted_reel(2, 0x4321)),
+        ];
+        assert!(join_files(inputs, &mut vec![], false).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+
+use crate::{
+    binfile::{BinFile, get_bin_confidence},
+    detect::FileType,
+    ext234::is_ext234_image_file,
+    file::File,
+    hdd::is_hdd_img_file,
+    hdr::get_hdr_confidence,
+    iso::is_iso_file,
+    snd::{SND_HEADER_LEN_WITH_ENCRYPTION, get_snd_confidence, is_encrypted_snd_header},
+    squash::is_squashfs_file,
+    vdisk::{decode_virtual_disk_from_file, get_virtual_disk_confidence, is_fixed_vhd_file},
+};
+
+/// Content confidence needed to accept a type without a matching extension
+pub const MIN_CONTENT_CONFIDENCE: u8 = 70;
+/// Content confidence above which no other probe is run
+pub const CERTAIN_CONFIDENCE: u8 = 90;
+/// Added to the content confidence when the extension matches
+pub const EXTENSION_CONFIDENCE: u8 = 20;
+
+/// Opens the image stored in a container, with a description of the container
+pub type UnwrapFn = fn(Box<dyn File>, bool) -> Result<(String, Box<dyn File>)>;
+
+/// Recognizes one file format. Adding a format only needs a new entry in
+/// FILE_PROBES.
+pub struct FileProbe {
+    pub name: &'static str,
+    /// Lowercase extensions usually used for this format
+    pub extensions: &'static [&'static str],
+    /// Returns the type the file is decoded as, and how likely (0 to 100) the
+    /// content is of this format. The lowercase extension of the file is
+    /// given, some formats have variants only told apart by it.
+    pub probe: fn(&mut dyn File, &str) -> (FileType, u8),
+    /// Only set for containers (virtual disks, raw CD images)
+    pub unwrap: Option<UnwrapFn>,
+}
+
+/// Ordered by how far into the file they need to read, which keeps the amount
+/// of data buffered for non-seekable inputs (stdin, pipes) small: probing
+/// stops at the first content confidence of CERTAIN_CONFIDENCE or more.
+pub static FILE_PROBES: [FileProbe; 9] = [
+    FileProbe {
+        name: "DTS XD HDR",
+        extensions: &["hdr"],
+        probe: probe_hdr,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "DTS SND/AUD/AUE",
+        extensions: &["aud", "aue", "snd"],
+        probe: probe_snd,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "SquashFS image",
+        extensions: &["squashfs", "sqfs"],
+        probe: probe_squashfs,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "virtual disk",
+        extensions: &["qcow2", "qcow", "vhd", "vmdk"],
+        probe: probe_virtual_disk,
+        unwrap: Some(unwrap_virtual_disk),
+    },
+    FileProbe {
+        name: "HDD image",
+        extensions: &["img"],
+        probe: probe_hdd_img,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "ext2/3/4 partition image",
+        extensions: &["ext2", "ext3", "ext4"],
+        probe: probe_partition_img,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "ISO 9660 image",
+        extensions: &["iso"],
+        probe: probe_iso,
+        unwrap: None,
+    },
+    FileProbe {
+        name: "BIN raw CD image",
+        extensions: &["bin"],
+        probe: probe_bin,
+        unwrap: Some(unwrap_bin),
+    },
+    // Last, fixed VHD disks only have a footer, at the end of the file. Only
+    // tried for the vhd extension: getting the length of a stream reads it all
+    FileProbe {
+        name: "fixed VHD virtual disk",
+        extensions: &["vhd"],
+        probe: probe_fixed_vhd,
+        unwrap: Some(unwrap_virtual_disk),
+    },
+];
+
+fn probe_hdr(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    (FileType::Hdr, get_hdr_confidence(file))
+}
+
+fn probe_snd(file: &mut dyn File, extension: &str) -> (FileType, u8) {
+    let confidence = get_snd_confidence(file);
+    let file_type = match extension {
+        "aud" => FileType::Aud,
+        "aue" => FileType::Aue,
+        "snd" => FileType::Snd,
+        _ => {
+            let encrypted = file
+                .read_bytes(SND_HEADER_LEN_WITH_ENCRYPTION)
+                .is_ok_and(|b| is_encrypted_snd_header(b[..].try_into().unwrap()));
+            if encrypted {
+                FileType::Aue
+            } else {
+                FileType::Aud
+            }
+        }
+    };
+    (file_type, confidence)
+}
+
+fn probe_squashfs(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    (
+        FileType::SquashFs,
+        get_magic_confidence(is_squashfs_file(file), 90),
+    )
+}
+
+fn probe_virtual_disk(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    (FileType::VirtualDisk, get_virtual_disk_confidence(file))
+}
+
+fn probe_hdd_img(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    // Only a 2 bytes signature, shared with any other MBR partitioned disk
+    (
+        FileType::HddImg,
+        get_magic_confidence(is_hdd_img_file(file), 70),
+    )
+}
+
+fn probe_partition_img(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    let confidence = get_magic_confidence(is_ext234_image_file(file), 90);
+    (FileType::PartitionImg, confidence)
+}
+
+fn probe_iso(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    (FileType::Iso, get_magic_confidence(is_iso_file(file), 90))
+}
+
+fn probe_bin(file: &mut dyn File, _extension: &str) -> (FileType, u8) {
+    (FileType::Bin, get_bin_confidence(file))
+}
+
+fn probe_fixed_vhd(file: &mut dyn File, extension: &str) -> (FileType, u8) {
+    let found = extension == "vhd" && is_fixed_vhd_file(file);
+    (FileType::VirtualDisk, get_magic_confidence(found, 80))
+}
+
+fn get_magic_confidence(found: bool, confidence: u8) -> u8 {
+    if found { confidence } else { 0 }
+}
+
+fn unwrap_virtual_disk(file: Box<dyn File>, verbose: bool) -> Result<(String, Box<dyn File>)> {
+    let disk = decode_virtual_disk_from_file(file, verbose)?;
+    Ok((
+        format!("{} virtual disk", disk.format_name()),
+        Box::new(disk),
+    ))
+}
+
+fn unwrap_bin(file: Box<dyn File>, _verbose: bool) -> Result<(String, Box<dyn File>)> {
+    let bin = BinFile::from_file(file)?;
+    Ok((
+        format!("BIN raw CD image (mode {} sectors)", bin.mode()),
+        Box::new(bin),
+    ))
+}
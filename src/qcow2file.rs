@@ -0,0 +1,229 @@
+use std::{collections::HashMap, io::Read};
+
+use anyhow::{Result, anyhow};
+use flate2::read::DeflateDecoder;
+
+use crate::{
+    file::File,
+    vdiskfile::{VirtualDiskFormat, read_be_u32, read_be_u64},
+};
+
+pub const QCOW2_MAGIC: [u8; 4] = [b'Q', b'F', b'I', 0xFB];
+const QCOW2_HEADER_LEN: usize = 104;
+
+const QCOW2_OFFSET_MASK: u64 = 0x00FF_FFFF_FFFF_FE00;
+const QCOW2_COMPRESSED_FLAG: u64 = 1 << 62;
+const QCOW2_ZERO_FLAG: u64 = 1;
+const QCOW2_INCOMPATIBLE_COMPRESSION_TYPE: u64 = 1 << 3;
+const QCOW2_INCOMPATIBLE_EXTERNAL_DATA: u64 = 1 << 2;
+const QCOW2_INCOMPATIBLE_EXTENDED_L2: u64 = 1 << 4;
+
+const SECTOR_LEN: u64 = 512;
+
+pub struct Qcow2Format {
+    file: Box<dyn File>,
+    cluster_bits: u32,
+    size: u64,
+    l1_table: Vec<u64>,
+    l2_tables: HashMap<u64, Vec<u64>>,
+}
+
+impl Qcow2Format {
+    pub fn from_file(mut file: Box<dyn File>) -> Result<Self> {
+        let header = file.read_bytes(QCOW2_HEADER_LEN)?;
+        if !header.starts_with(&QCOW2_MAGIC) {
+            return Err(anyhow!("Not a qcow2 file"));
+        }
+        let version = read_be_u32(&header, 4);
+        if version != 2 && version != 3 {
+            return Err(anyhow!("Unsupported qcow2 version: {}", version));
+        }
+        if read_be_u64(&header, 8) != 0 {
+            return Err(anyhow!("qcow2 files with a backing file are not supported"));
+        }
+        let cluster_bits = read_be_u32(&header, 20);
+        if !(9..=21).contains(&cluster_bits) {
+            return Err(anyhow!("Invalid qcow2 cluster size: 2^{}", cluster_bits));
+        }
+        let size = read_be_u64(&header, 24);
+        if read_be_u32(&header, 32) != 0 {
+            return Err(anyhow!("Encrypted qcow2 files are not supported"));
+        }
+        if version == 3 {
+            let incompatible = read_be_u64(&header, 72);
+            if incompatible & QCOW2_INCOMPATIBLE_COMPRESSION_TYPE != 0 {
+                return Err(anyhow!("Only zlib compressed qcow2 files are supported"));
+            }
+            if incompatible & QCOW2_INCOMPATIBLE_EXTERNAL_DATA != 0 {
+                return Err(anyhow!("qcow2 files with external data are not supported"));
+            }
+            if incompatible & QCOW2_INCOMPATIBLE_EXTENDED_L2 != 0 {
+                return Err(anyhow!(
+                    "qcow2 files with extended L2 entries are not supported"
+                ));
+            }
+        }
+        let l1_size = read_be_u32(&header, 36) as usize;
+        let l1_table_offset = read_be_u64(&header, 40);
+        let l1_bytes = file.read_exact_bytes_at(l1_size * 8, l1_table_offset)?;
+        let l1_table = (0..l1_size)
+            .map(|i| read_be_u64(&l1_bytes, i * 8))
+            .collect();
+
+        Ok(Qcow2Format {
+            file,
+            cluster_bits,
+            size,
+            l1_table,
+            l2_tables: HashMap::new(),
+        })
+    }
+
+    fn cluster_len(&self) -> u64 {
+        1 << self.cluster_bits
+    }
+
+    fn get_l2_entry(&mut self, index: u64) -> Result<u64> {
+        let entries_per_table = self.cluster_len() / 8;
+        let l1_index = (index / entries_per_table) as usize;
+        let l2_index = (index % entries_per_table) as usize;
+        let l2_offset = match self.l1_table.get(l1_index) {
+            Some(entry) => entry & QCOW2_OFFSET_MASK,
+            None => return Ok(0),
+        };
+        if l2_offset == 0 {
+            return Ok(0);
+        }
+        if !self.l2_tables.contains_key(&l2_offset) {
+            let bytes = self
+                .file
+                .read_exact_bytes_at(self.cluster_len() as usize, l2_offset)?;
+            let table = (0..entries_per_table as usize)
+                .map(|i| read_be_u64(&bytes, i * 8))
+                .collect();
+            self.l2_tables.insert(l2_offset, table);
+        }
+        Ok(self.l2_tables[&l2_offset][l2_index])
+    }
+
+    fn read_compressed_cluster(&mut self, entry: u64) -> Result<Vec<u8>> {
+        let offset_bits = 62 - (self.cluster_bits - 8);
+        let offset = entry & ((1 << offset_bits) - 1);
+        let additional_sectors = (entry & !QCOW2_COMPRESSED_FLAG) >> offset_bits;
+        let len = (additional_sectors + 1) * SECTOR_LEN - (offset % SECTOR_LEN);
+        let available = self.file.len()?.saturating_sub(offset);
+        let compressed = self
+            .file
+            .read_exact_bytes_at(len.min(available) as usize, offset)?;
+
+        let mut cluster = vec![0; self.cluster_len() as usize];
+        let mut decoder = DeflateDecoder::new(&compressed[..]);
+        decoder.read_exact(&mut cluster).map_err(|e| {
+            anyhow!(
+                "Could not decompress qcow2 cluster at offset {}: {}",
+                offset,
+                e
+            )
+        })?;
+        Ok(cluster)
+    }
+}
+
+impl VirtualDiskFormat for Qcow2Format {
+    fn name(&self) -> &'static str {
+        "qcow2"
+    }
+
+    fn disk_len(&self) -> u64 {
+        self.size
+    }
+
+    fn block_len(&self) -> u64 {
+        self.cluster_len()
+    }
+
+    fn read_block(&mut self, index: u64) -> Result<Vec<u8>> {
+        let entry = self.get_l2_entry(index)?;
+        if entry & QCOW2_COMPRESSED_FLAG != 0 {
+            return self.read_compressed_cluster(entry);
+        }
+        let offset = entry & QCOW2_OFFSET_MASK;
+        if offset == 0 || entry & QCOW2_ZERO_FLAG != 0 {
+            return Ok(vec![0; self.cluster_len() as usize]);
+        }
+        self.file
+            .read_exact_bytes_at(self.cluster_len() as usize, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::DeflateEncoder};
+
+    use super::*;
+    use crate::{memfile::MemoryFile, vdiskfile::VirtualDiskFile};
+
+    const CLUSTER_BITS: u32 = 9;
+    const CLUSTER_LEN: usize = 1 << CLUSTER_BITS;
+
+    /// Builds a 4 clusters image: plain, compressed, unallocated and zero flagged
+    fn build_image(plain: &[u8], compressed: &[u8]) -> Vec<u8> {
+        let mut image = vec![0; CLUSTER_LEN * 3];
+        image[0..4].copy_from_slice(&QCOW2_MAGIC);
+        image[4..8].copy_from_slice(&3u32.to_be_bytes());
+        image[20..24].copy_from_slice(&CLUSTER_BITS.to_be_bytes());
+        image[24..32].copy_from_slice(&(4 * CLUSTER_LEN as u64).to_be_bytes());
+        image[36..40].copy_from_slice(&1u32.to_be_bytes());
+        // L1 table in cluster 1, L2 table in cluster 2
+        image[40..48].copy_from_slice(&(CLUSTER_LEN as u64).to_be_bytes());
+        image[100..104].copy_from_slice(&104u32.to_be_bytes());
+        let l2_offset = 2 * CLUSTER_LEN as u64;
+        image[CLUSTER_LEN..CLUSTER_LEN + 8].copy_from_slice(&l2_offset.to_be_bytes());
+
+        let plain_offset = image.len() as u64;
+        image.extend_from_slice(plain);
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::best());
+        encoder.write_all(compressed).unwrap();
+        let deflated = encoder.finish().unwrap();
+        let compressed_offset = image.len() as u64 + 100;
+        image.resize(compressed_offset as usize, 0);
+        image.extend_from_slice(&deflated);
+        let end = compressed_offset + deflated.len() as u64;
+        let sectors = (end - 1) / 512 - compressed_offset / 512;
+        let offset_bits = 62 - (CLUSTER_BITS - 8);
+        let compressed_entry = QCOW2_COMPRESSED_FLAG | (sectors << offset_bits) | compressed_offset;
+
+        let l2 = l2_offset as usize;
+        image[l2..l2 + 8].copy_from_slice(&plain_offset.to_be_bytes());
+        image[l2 + 8..l2 + 16].copy_from_slice(&compressed_entry.to_be_bytes());
+        image[l2 + 24..l2 + 32].copy_from_slice(&(plain_offset | QCOW2_ZERO_FLAG).to_be_bytes());
+        image
+    }
+
+    #[test]
+    fn test_read_clusters() {
+        let plain: Vec<u8> = (0..CLUSTER_LEN).map(|i| i as u8).collect();
+        let compressed: Vec<u8> = (0..CLUSTER_LEN).map(|i| (i / 16) as u8).collect();
+        let image = build_image(&plain, &compressed);
+
+        let format = Qcow2Format::from_file(Box::new(MemoryFile::from(image))).unwrap();
+        let mut file = VirtualDiskFile::new(Box::new(format));
+        assert_eq!(file.len().unwrap(), 4 * CLUSTER_LEN as u64);
+
+        let mut disk = vec![];
+        file.read_to_end(&mut disk).unwrap();
+        assert_eq!(disk[..CLUSTER_LEN], plain[..]);
+        assert_eq!(disk[CLUSTER_LEN..2 * CLUSTER_LEN], compressed[..]);
+        assert!(disk[2 * CLUSTER_LEN..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_extended_l2_rejected() {
+        let mut image = build_image(&[0; CLUSTER_LEN], &[0; CLUSTER_LEN]);
+        image[72..80].copy_from_slice(&QCOW2_INCOMPATIBLE_EXTENDED_L2.to_be_bytes());
+        assert!(Qcow2Format::from_file(Box::new(MemoryFile::from(image))).is_err());
+    }
+}
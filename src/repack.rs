@@ -0,0 +1,527 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    audio::AudioLayout,
+    file::{DirEntry, File, FileSystem, FileType, WritableFileSystem},
+    hdr::write_hdr,
+    info::open_input_file,
+    metadata::{
+        Offset, Revision, SndFileMetadata, SndType, TrailersMetadata, TrailersMetadataTxtEntry,
+        XDMetadata,
+    },
+    osfile::OsFileSystem,
+    platter::copy_bytes,
+    snd::{
+        SND_HEADER_LEN_WITH_ENCRYPTION, decode_snd_header, decode_snd_header_from_file,
+        encode_header, get_snd_file_stem,
+    },
+    trailers::{TRAILER_UNIT_LEN, decode_trailers_from_txt_file, encode_trailers_to_txt_file},
+    unionfile::UnionFileSystem,
+};
+
+const TRAILERS_AUDIO_NAMES: [&str; 2] = ["r14t5.aud", "r14t5.aue"];
+const TRAILERS_TXT_NAMES: [&str; 2] = ["r14trlr.txt", "r14.txt"];
+
+/// Tracks of the trailers of a trailer reel, the units of R14TRLR.TXT are
+/// frames of 5 tracks
+const TRAILER_TRACKS: u8 = 5;
+
+/// A trailer of the repacked reel: its line of R14TRLR.TXT, the offset being
+/// recomputed, and where its audio is.
+struct Trailer {
+    entry: TrailersMetadataTxtEntry,
+    /// Index of the file holding the audio
+    file: usize,
+    offset: u64,
+    len: u64,
+    origin: String,
+}
+
+/// Trailers taken from R14T5.AUD and R14TRLR.TXT or from SND files
+struct TrailerPool {
+    files: Vec<Box<dyn File>>,
+    trailers: Vec<Trailer>,
+}
+
+/// Rewrites the trailer reel of INPUT (a directory, an ISO file or a drive
+/// image) without the dropped trailers, with the added ones after the others,
+/// and with the ordered ones first. The audio of the trailers is copied as
+/// is. With `split`, each trailer is written as a SND and HDR file instead.
+/// Without an output, only the resulting list is shown.
+pub fn repack_trailers(
+    input: &Path,
+    output: Option<&Path>,
+    drop: &[u16],
+    add: &[PathBuf],
+    order: &[u16],
+    split: bool,
+    verbose: bool,
+) -> Result<()> {
+    let mut pool = TrailerPool {
+        files: vec![],
+        trailers: vec![],
+    };
+    let header = pool.add_trailer_reel(input, verbose)?;
+    for id in drop {
+        let count = pool.trailers.len();
+        pool.trailers.retain(|t| t.entry.id != *id);
+        if pool.trailers.len() == count {
+            return Err(anyhow!("No trailer with id {} in {}", id, input.display()));
+        }
+    }
+    for path in add {
+        if is_snd_path(path) {
+            pool.add_snd(path)?;
+        } else {
+            pool.add_trailer_reel(path, verbose)?;
+        }
+    }
+    pool.check_ids()?;
+    pool.reorder(order)?;
+
+    println!("Trailers:");
+    for (i, t) in pool.trailers.iter().enumerate() {
+        println!(
+            "  {}. Id: {}, Title: {}, Duration: {}, from {}",
+            i + 1,
+            t.entry.id,
+            t.entry.title,
+            t.entry.duration().to_clock_string(),
+            t.origin
+        );
+    }
+    let Some(output) = output else {
+        return Ok(());
+    };
+    check_output(input, output)?;
+    if split {
+        let reel = decode_reel_header(pool.files[0].as_mut())?;
+        return pool.write_trailer_files(&reel, output, verbose);
+    }
+
+    let mut output_fs = OsFileSystem;
+    output_fs.create_dir_all(output)?;
+    let snd_path = output.join(TRAILERS_AUDIO_NAMES[0]);
+    let txt_path = output.join(TRAILERS_TXT_NAMES[0]);
+    let mut snd_file = output_fs.create_file(&snd_path)?;
+    let metadata = pool.write(&header, &mut snd_file, verbose)?;
+    snd_file.flush()?;
+    println!("Created {:?}", snd_path);
+
+    let mut txt_file = output_fs.create_file(&txt_path)?;
+    encode_trailers_to_txt_file(&mut txt_file, &metadata)?;
+    txt_file.flush()?;
+    println!("Created {:?}", txt_path);
+    Ok(())
+}
+
+impl TrailerPool {
+    /// Adds the trailers of R14T5.AUD in the order of R14TRLR.TXT, returns the
+    /// header of R14T5.AUD
+    fn add_trailer_reel(&mut self, path: &Path, verbose: bool) -> Result<Vec<u8>> {
+        let mut union = UnionFileSystem::from_paths(&[path], verbose)?;
+        let mut audio_path = None;
+        let mut txt_path = None;
+        for e in union.read_dir("/")? {
+            if !matches!(e.file_type(), Ok(FileType::File)) {
+                continue;
+            }
+            let name = e.file_name().to_ascii_lowercase();
+            if TRAILERS_AUDIO_NAMES.contains(&name.as_str()) {
+                audio_path = Some(e.path()?);
+            } else if TRAILERS_TXT_NAMES.contains(&name.as_str()) {
+                txt_path = Some(e.path()?);
+            }
+        }
+        let (Some(audio_path), Some(txt_path)) = (audio_path, txt_path) else {
+            return Err(anyhow!(
+                "No trailer reel (R14T5.AUD and R14TRLR.TXT) found in {}",
+                path.display()
+            ));
+        };
+        let mut txt_file = union.open_file(&txt_path)?;
+        let metadata = decode_trailers_from_txt_file(&mut txt_file, &txt_path)?;
+        if verbose {
+            println!(
+                "add_trailer_reel: {} trailers in {:?}",
+                metadata.entries.len(),
+                audio_path
+            );
+        }
+        let audio = Box::new(union.open_file(&audio_path)?);
+        self.add_trailers(audio, metadata, &path.display().to_string())
+    }
+
+    fn add_trailers(
+        &mut self,
+        mut audio: Box<dyn File>,
+        metadata: TrailersMetadata,
+        origin: &str,
+    ) -> Result<Vec<u8>> {
+        let layout = check_trailer_layout(audio.as_mut(), origin)?;
+        let header = audio.read_exact_bytes_at(layout.header_len as usize, 0)?;
+
+        // The audio of a trailer goes up to the next one, an incomplete last
+        // frame included
+        let mut offsets: Vec<u64> = metadata.entries.iter().map(|e| e.offset as u64).collect();
+        offsets.push(audio.len()?);
+        offsets.sort();
+        let file = self.files.len();
+        for entry in metadata.entries {
+            let offset = entry.offset as u64;
+            let next = offsets.iter().find(|o| **o > offset).copied();
+            let (true, Some(next)) = (offset >= layout.header_len, next) else {
+                return Err(anyhow!(
+                    "Trailer {} ({}) is out of the audio of {}",
+                    entry.id,
+                    entry.title,
+                    origin
+                ));
+            };
+            self.trailers.push(Trailer {
+                entry,
+                file,
+                offset,
+                len: next - offset,
+                origin: origin.to_string(),
+            });
+        }
+        self.files.push(audio);
+        Ok(header)
+    }
+
+    /// Adds the trailer of a SND file, its title becomes the name of the
+    /// trailer, spaces being replaced
+    fn add_snd(&mut self, path: &Path) -> Result<()> {
+        let mut file = open_input_file(path)?;
+        let metadata = decode_snd_header_from_file(file.as_mut(), path)?;
+        if !matches!(metadata.snd_type, SndType::Trailer) {
+            return Err(anyhow!(
+                "{} is reel {} of a feature, not a trailer",
+                path.display(),
+                metadata.reel
+            ));
+        }
+        let layout = check_trailer_layout(file.as_mut(), &path.display().to_string())?;
+        let len = file.len()?.saturating_sub(layout.header_len);
+        let title = metadata
+            .title
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("_");
+        self.trailers.push(Trailer {
+            entry: TrailersMetadataTxtEntry {
+                title,
+                id: metadata.id,
+                start: 0,
+                end: len as usize / TRAILER_UNIT_LEN,
+                offset: 0,
+            },
+            file: self.files.len(),
+            offset: layout.header_len,
+            len,
+            origin: path.display().to_string(),
+        });
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Trailers are chosen by id, an id has to be found once
+    fn check_ids(&self) -> Result<()> {
+        for (i, t) in self.trailers.iter().enumerate() {
+            if let Some(other) = self.trailers[i + 1..]
+                .iter()
+                .find(|o| o.entry.id == t.entry.id)
+            {
+                return Err(anyhow!(
+                    "Trailer id {} found twice: {} from {} and {} from {}",
+                    t.entry.id,
+                    t.entry.title,
+                    t.origin,
+                    other.entry.title,
+                    other.origin
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the given trailers first, in the given order, the others keep
+    /// theirs
+    fn reorder(&mut self, order: &[u16]) -> Result<()> {
+        let mut ordered = Vec::with_capacity(self.trailers.len());
+        for id in order {
+            let Some(i) = self.trailers.iter().position(|t| t.entry.id == *id) else {
+                return Err(anyhow!("No trailer with id {} to order", id));
+            };
+            ordered.push(self.trailers.remove(i));
+        }
+        ordered.append(&mut self.trailers);
+        self.trailers = ordered;
+        Ok(())
+    }
+
+    /// Writes each trailer as a SND file, from START to END of its line of
+    /// R14TRLR.TXT, with its HDR file
+    fn write_trailer_files(
+        &mut self,
+        reel: &SndFileMetadata,
+        output: &Path,
+        verbose: bool,
+    ) -> Result<()> {
+        let mut output_fs = OsFileSystem;
+        output_fs.create_dir_all(output)?;
+        for t in &self.trailers {
+            let start = t.entry.start as u64 * TRAILER_UNIT_LEN as u64;
+            let end = t.entry.end as u64 * TRAILER_UNIT_LEN as u64;
+            if start > end || end > t.len {
+                return Err(anyhow!(
+                    "Trailer {} ({}) goes from {} to {}, out of its {} bytes of audio",
+                    t.entry.id,
+                    t.entry.title,
+                    start,
+                    end,
+                    t.len
+                ));
+            }
+            let metadata = get_trailer_metadata(reel, &t.entry);
+            let snd_path = output
+                .join(get_snd_file_stem(metadata.id, metadata.reel))
+                .with_extension("snd");
+            let mut snd_file = output_fs.create_file(&snd_path)?;
+            snd_file.write_all(&encode_header(&metadata)?)?;
+            let mut md5 = md5::Context::new();
+            copy_bytes(
+                self.files[t.file].as_mut(),
+                t.offset + start,
+                end - start,
+                &mut snd_file,
+                &mut md5,
+            )?;
+            snd_file.flush()?;
+            if verbose {
+                println!(
+                    "write_trailer_files: trailer {}, {} audio bytes, md5 {:x}",
+                    t.entry.id,
+                    end - start,
+                    md5.finalize()
+                );
+            }
+            println!("Created {:?}", snd_path);
+            write_hdr(&mut output_fs, &snd_path.with_extension("hdr"), &metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the header then the audio of the trailers, returns the entries
+    /// of R14TRLR.TXT with their new offsets
+    fn write(
+        &mut self,
+        header: &[u8],
+        output: &mut dyn Write,
+        verbose: bool,
+    ) -> Result<TrailersMetadata> {
+        output.write_all(header)?;
+        let mut metadata = TrailersMetadata { entries: vec![] };
+        let mut offset = header.len();
+        for t in &self.trailers {
+            let mut md5 = md5::Context::new();
+            copy_bytes(
+                self.files[t.file].as_mut(),
+                t.offset,
+                t.len,
+                output,
+                &mut md5,
+            )?;
+            if verbose {
+                println!(
+                    "write: trailer {} at {}, {} bytes, md5 {:x}",
+                    t.entry.id,
+                    offset,
+                    t.len,
+                    md5.finalize()
+                );
+            }
+            metadata.entries.push(TrailersMetadataTxtEntry {
+                title: t.entry.title.clone(),
+                id: t.entry.id,
+                start: t.entry.start,
+                end: t.entry.end,
+                offset,
+            });
+            offset += t.len as usize;
+        }
+        Ok(metadata)
+    }
+}
+
+/// Header of a trailer SND: the one of the trailer reel, but for the id, the
+/// title and the end offset, the length of the trailer
+fn get_trailer_metadata(
+    reel: &SndFileMetadata,
+    entry: &TrailersMetadataTxtEntry,
+) -> SndFileMetadata {
+    SndFileMetadata {
+        revision: Revision::XD,
+        snd_type: SndType::Trailer,
+        id: entry.id,
+        reel: reel.reel,
+        title: entry.title.clone(),
+        studio: None,
+        optical_backup: reel.optical_backup,
+        tracks: TRAILER_TRACKS,
+        start_offset: None,
+        end_offset: Some(Offset::from_timecode(entry.duration())),
+        encryption_key: None,
+        xd: Some(XDMetadata {
+            language: reel.xd.as_ref().and_then(|xd| xd.language.clone()),
+            xda: None,
+        }),
+        raw_header: reel.raw_header.clone(),
+        warnings: vec![],
+    }
+}
+
+fn decode_reel_header(file: &mut dyn File) -> Result<SndFileMetadata> {
+    let bytes = file.read_exact_bytes_at(SND_HEADER_LEN_WITH_ENCRYPTION, 0)?;
+    decode_snd_header(file, bytes[..].try_into()?)
+}
+
+/// Trailers are copied as they are: they need the 5 tracks of the trailer
+/// reel, without encryption
+fn check_trailer_layout(file: &mut dyn File, origin: &str) -> Result<AudioLayout> {
+    let layout = AudioLayout::from_file(file)?;
+    if layout.encrypted {
+        return Err(anyhow!("{} is encrypted", origin));
+    }
+    if layout.tracks != TRAILER_TRACKS {
+        return Err(anyhow!(
+            "{} has {} tracks, trailers have {}",
+            origin,
+            layout.tracks,
+            TRAILER_TRACKS
+        ));
+    }
+    Ok(layout)
+}
+
+fn is_snd_path(path: &Path) -> bool {
+    match path.extension() {
+        Some(e) => matches!(
+            e.to_string_lossy().to_ascii_lowercase().as_str(),
+            "snd" | "aud" | "aue"
+        ),
+        None => false,
+    }
+}
+
+/// The input files are read while the output is written, they can not be the
+/// same
+fn check_output(input: &Path, output: &Path) -> Result<()> {
+    let (Ok(input), Ok(output)) = (input.canonicalize(), output.canonicalize()) else {
+        return Ok(());
+    };
+    if output == input || output == input.join("dts") {
+        return Err(anyhow!(
+            "The output can not be the directory of the input trailer reel"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memfile::MemoryFile,
+        snd::{SND_HEADER_LEN, encode_header, get_generic_trailers_header},
+    };
+
+    /// A trailer reel holding trailers of 1, 2 and 3 frames, each made of 0xA0
+    /// plus its id, with an incomplete frame after the last one
+    fn get_trailer_reel() -> (Vec<u8>, TrailersMetadata) {
+        let mut reel = encode_header(&get_generic_trailers_header()).unwrap();
+        let mut metadata = TrailersMetadata { entries: vec![] };
+        for id in 1..=3u16 {
+            metadata.entries.push(TrailersMetadataTxtEntry {
+                title: format!("TRAILER-{}", id),
+                id,
+                start: 0,
+                end: id as usize,
+                offset: reel.len(),
+            });
+            reel.extend(std::iter::repeat_n(
+                0xA0 + id as u8,
+                id as usize * TRAILER_UNIT_LEN,
+            ));
+        }
+        reel.extend([0xA3; 100]);
+        (reel, metadata)
+    }
+
+    #[test]
+    fn test_repack_trailers() {
+        let (reel, metadata) = get_trailer_reel();
+        let mut pool = TrailerPool {
+            files: vec![],
+            trailers: vec![],
+        };
+        let header = pool
+            .add_trailers(Box::new(MemoryFile::from(reel.clone())), metadata, "reel")
+            .unwrap();
+        assert_eq!(header, reel[..SND_HEADER_LEN]);
+        assert_eq!(pool.trailers[2].len, 3 * TRAILER_UNIT_LEN as u64 + 100);
+        pool.trailers.retain(|t| t.entry.id != 2);
+        pool.reorder(&[3]).unwrap();
+        assert!(pool.reorder(&[2]).is_err());
+
+        let mut output = vec![];
+        let metadata = pool.write(&header, &mut output, false).unwrap();
+        let ids: Vec<u16> = metadata.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+        assert_eq!(metadata.entries[0].offset, SND_HEADER_LEN);
+        assert_eq!(metadata.entries[0].end, 3);
+        assert_eq!(
+            metadata.entries[1].offset,
+            SND_HEADER_LEN + 3 * TRAILER_UNIT_LEN + 100
+        );
+        assert_eq!(output[..SND_HEADER_LEN], reel[..SND_HEADER_LEN]);
+        assert!(
+            output[metadata.entries[0].offset..metadata.entries[1].offset]
+                .iter()
+                .all(|b| *b == 0xA3)
+        );
+        assert!(
+            output[metadata.entries[1].offset..]
+                .iter()
+                .all(|b| *b == 0xA1)
+        );
+        assert_eq!(output.len(), reel.len() - 2 * TRAILER_UNIT_LEN);
+    }
+
+    #[test]
+    fn test_trailer_metadata() {
+        let reel = get_generic_trailers_header();
+        let entry = TrailersMetadataTxtEntry {
+            title: "MY-TRAILER".to_string(),
+            id: 4321,
+            start: 0,
+            end: 90,
+            offset: SND_HEADER_LEN,
+        };
+        let header = encode_header(&get_trailer_metadata(&reel, &entry)).unwrap();
+        assert_eq!(header.len(), SND_HEADER_LEN);
+        let mut file = MemoryFile::from([header, vec![0; TRAILER_UNIT_LEN]].concat());
+        let decoded = decode_reel_header(&mut file).unwrap();
+        assert_eq!(decoded.id, 4321);
+        assert_eq!(decoded.reel, 14);
+        assert_eq!(decoded.title, "MY-TRAILER");
+        assert_eq!(decoded.tracks, 5);
+        assert_eq!(decoded.end_offset, Some(Offset::from_frames(90)));
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+use crate::{
+    file::{DirEntry, File, FileSystem, FileType},
+    iso::is_iso_file,
+    isofile::IsoFileSystem,
+    osfile::{OsFile, OsFileSystem, is_device_path},
+    rescuefile::{RescueFile, decode_rescue_map_from_file},
+    snd::decode_snd_header_from_file,
+    trailers::{TRAILER_UNIT_LEN, decode_trailers_from_txt_file},
+};
+
+const TRAILERS_AUDIO_NAMES: [&str; 2] = ["r14t5.aud", "r14t5.aue"];
+const TRAILERS_TXT_NAMES: [&str; 2] = ["r14trlr.txt", "r14.txt"];
+
+pub enum DtsFileKind {
+    Reel(u8),
+    TrailersAudio,
+    TrailersTxt,
+    Other,
+}
+
+pub struct DamagedFile {
+    pub path: PathBuf,
+    pub kind: DtsFileKind,
+    /// Unrecovered areas, relative to the start of the file
+    pub bad: Vec<Range<u64>>,
+}
+
+pub struct DamagedTrailer {
+    pub id: u16,
+    pub title: String,
+    pub offset: usize,
+    /// Unrecovered units, relative to the start of the trailer
+    pub bad_units: Vec<Range<usize>>,
+}
+
+pub struct RescueReport {
+    pub image_len: u64,
+    pub reels: Vec<(u8, PathBuf)>,
+    pub bad: Vec<Range<u64>>,
+    pub files: Vec<DamagedFile>,
+    pub trailers: Result<Vec<DamagedTrailer>>,
+    /// Unrecovered bytes not belonging to any file (system area, directories,
+    /// free space)
+    pub outside_files: u64,
+}
+
+impl RescueReport {
+    pub fn bad_len(&self) -> u64 {
+        get_ranges_len(&self.bad)
+    }
+}
+
+/// Opens an image rescued by ddrescue, reads of unrecovered sectors fail.
+pub fn open_rescued_image(path: &Path, mapfile: &Path) -> Result<RescueFile> {
+    let mut os_fs = OsFileSystem;
+    let map = decode_rescue_map_from_file(&mut os_fs.open_file(mapfile)?, mapfile)?;
+    let file: Box<dyn File> = if os_fs.is_file(path) {
+        Box::new(os_fs.open_file(path)?)
+    } else if is_device_path(path) {
+        Box::new(OsFile::open_device(path)?)
+    } else {
+        return Err(anyhow!("Could not open input {}", path.display()));
+    };
+    Ok(RescueFile::new(file, map))
+}
+
+/// Finds which files of the ISO image, and which trailers of R14T5.AUD,
+/// overlap the unrecovered areas of the map.
+pub fn analyze_rescued_iso(mut file: RescueFile, verbose: bool) -> Result<RescueReport> {
+    if !is_iso_file(&mut file) {
+        return Err(anyhow!(
+            "Could not read the ISO header, mapfiles are only supported for ISO images"
+        ));
+    }
+    let map = file.map().clone();
+    let image_len = file.len()?.max(map.len());
+    let bad = map.bad_ranges_in(0..image_len);
+
+    let mut fs = IsoFileSystem::from_file(Box::new(file))?;
+    let mut extents = vec![];
+    collect_file_extents(&mut fs, Path::new("/"), &mut extents, verbose)?;
+
+    let reel_re = Regex::new(r"^r([1-9]|1[0-3])t5\.(aud|aue)$").unwrap();
+    let mut files = vec![];
+    let mut reels = vec![];
+    let mut trailers_audio_bad: Option<Vec<Range<u64>>> = None;
+    let mut trailers_txt: Option<PathBuf> = None;
+    for (path, extent) in &extents {
+        let name = get_lowercase_name(path);
+        let kind = if TRAILERS_AUDIO_NAMES.contains(&name.as_str()) {
+            DtsFileKind::TrailersAudio
+        } else if TRAILERS_TXT_NAMES.contains(&name.as_str()) {
+            trailers_txt = Some(path.clone());
+            DtsFileKind::TrailersTxt
+        } else if let Some(c) = reel_re.captures(&name) {
+            let reel = c[1].parse()?;
+            reels.push((reel, path.clone()));
+            DtsFileKind::Reel(reel)
+        } else {
+            DtsFileKind::Other
+        };
+        let file_bad: Vec<Range<u64>> = map
+            .bad_ranges_in(extent.clone())
+            .into_iter()
+            .map(|r| r.start - extent.start..r.end - extent.start)
+            .collect();
+        if let DtsFileKind::TrailersAudio = kind {
+            trailers_audio_bad = Some(file_bad.clone());
+        }
+        if !file_bad.is_empty() {
+            files.push(DamagedFile {
+                path: path.clone(),
+                kind,
+                bad: file_bad,
+            });
+        }
+    }
+
+    let trailers = match (trailers_audio_bad, trailers_txt) {
+        (Some(audio_bad), Some(txt_path)) => find_damaged_trailers(&mut fs, &txt_path, &audio_bad),
+        _ => Ok(vec![]),
+    };
+
+    let in_files: u64 = extents
+        .iter()
+        .map(|(_, e)| get_ranges_len(&map.bad_ranges_in(e.clone())))
+        .sum();
+    let outside_files = get_ranges_len(&bad).saturating_sub(in_files);
+
+    reels.sort();
+
+    Ok(RescueReport {
+        image_len,
+        reels,
+        bad,
+        files,
+        trailers,
+        outside_files,
+    })
+}
+
+pub fn print_rescue_report(report: &RescueReport) {
+    println!(
+        "Rescue map: {} unrecovered areas, {} of {} bytes ({:.2}%)",
+        report.bad.len(),
+        report.bad_len(),
+        report.image_len,
+        report.bad_len() as f64 * 100.0 / report.image_len.max(1) as f64
+    );
+    if report.files.is_empty() {
+        println!("  No damaged file found");
+    }
+    for f in &report.files {
+        println!(
+            "  {}{}: {} bytes unrecovered",
+            f.path.display(),
+            match f.kind {
+                DtsFileKind::Reel(reel) => format!(" (reel {})", reel),
+                DtsFileKind::TrailersAudio => " (trailers audio)".to_string(),
+                DtsFileKind::TrailersTxt => " (trailers list)".to_string(),
+                DtsFileKind::Other => String::new(),
+            },
+            get_ranges_len(&f.bad)
+        );
+        for r in &f.bad {
+            println!("    {:#x}..{:#x}", r.start, r.end);
+        }
+    }
+    match &report.trailers {
+        Ok(trailers) => {
+            for t in trailers {
+                println!(
+                    "  Trailer {} ({}), offset {}: units {}",
+                    t.id,
+                    t.title,
+                    t.offset,
+                    t.bad_units
+                        .iter()
+                        .map(|r| format!("{}..{}", r.start, r.end))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+            }
+        }
+        Err(e) => println!("  Warning: could not find damaged trailers: {}", e),
+    }
+    if report.outside_files > 0 {
+        println!(
+            "  {} unrecovered bytes outside of files (system area, directories or free space)",
+            report.outside_files
+        );
+    }
+}
+
+/// Validation pass: prints the damage report and checks every reel header can
+/// be read, fails if a DTS file is damaged.
+pub fn validate_rescued_image(path: &Path, mapfile: &Path, verbose: bool) -> Result<()> {
+    let report = analyze_rescued_iso(open_rescued_image(path, mapfile)?, verbose)?;
+    print_rescue_report(&report);
+
+    print_reels_check(path, mapfile, &report)?;
+
+    let damaged = report
+        .files
+        .iter()
+        .filter(|f| !matches!(f.kind, DtsFileKind::Other))
+        .count();
+    if damaged > 0 {
+        return Err(anyhow!("{} DTS files are damaged", damaged));
+    }
+    println!("All DTS files were recovered");
+    Ok(())
+}
+
+/// Decodes the header of every reel and prints what is still missing from it.
+pub fn print_reels_check(path: &Path, mapfile: &Path, report: &RescueReport) -> Result<()> {
+    let mut fs = IsoFileSystem::from_file(Box::new(open_rescued_image(path, mapfile)?))?;
+    for (reel, reel_path) in &report.reels {
+        let header = match decode_snd_header_from_file(&mut fs.open_file(reel_path)?, reel_path) {
+            Ok(_) => "header readable".to_string(),
+            Err(e) => format!("header unreadable ({})", e),
+        };
+        let gaps = match report.files.iter().find(|f| &f.path == reel_path) {
+            Some(f) => format!(
+                "{} bytes missing in {} areas",
+                get_ranges_len(&f.bad),
+                f.bad.len()
+            ),
+            None => "complete".to_string(),
+        };
+        println!("  Reel {}: {}, {}", reel, header, gaps);
+    }
+    Ok(())
+}
+
+fn find_damaged_trailers(
+    fs: &mut IsoFileSystem,
+    txt_path: &Path,
+    audio_bad: &[Range<u64>],
+) -> Result<Vec<DamagedTrailer>> {
+    let mut txt = fs.open_file(txt_path)?;
+    let metadata = decode_trailers_from_txt_file(&mut txt, txt_path)?;
+    let mut trailers = vec![];
+    for e in metadata.entries {
+        let start = e.offset as u64 + e.start as u64 * TRAILER_UNIT_LEN as u64;
+        let end = e.offset as u64 + e.end as u64 * TRAILER_UNIT_LEN as u64;
+        let bad_units: Vec<Range<usize>> = audio_bad
+            .iter()
+            .filter(|r| r.start < end && r.end > start)
+            .map(|r| {
+                let first = (r.start.max(start) - start) as usize / TRAILER_UNIT_LEN;
+                let last = ((r.end.min(end) - start) as usize).div_ceil(TRAILER_UNIT_LEN);
+                first..last
+            })
+            .collect();
+        if !bad_units.is_empty() {
+            trailers.push(DamagedTrailer {
+                id: e.id,
+                title: e.title,
+                offset: e.offset,
+                bad_units,
+            });
+        }
+    }
+    Ok(trailers)
+}
+
+fn collect_file_extents(
+    fs: &mut IsoFileSystem,
+    path: &Path,
+    extents: &mut Vec<(PathBuf, Range<u64>)>,
+    verbose: bool,
+) -> Result<()> {
+    for e in fs.read_dir(path)? {
+        // Records for the current and parent directories
+        if e.file_name().chars().all(|c| c < ' ') {
+            continue;
+        }
+        let entry_path = e.path()?;
+        match e.file_type()? {
+            FileType::Directory => collect_file_extents(fs, &entry_path, extents, verbose)?,
+            FileType::File => {
+                let extent = fs.get_file_extent(&entry_path)?;
+                if verbose {
+                    println!(
+                        "  {}: {:#x}..{:#x}",
+                        entry_path.display(),
+                        extent.start,
+                        extent.end
+                    );
+                }
+                extents.push((entry_path, extent));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_lowercase_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn get_ranges_len(ranges: &[Range<u64>]) -> u64 {
+    ranges.iter().map(|r| r.end - r.start).sum()
+}
@@ -0,0 +1,339 @@
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::file::File;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RescueStatus {
+    NonTried,
+    NonTrimmed,
+    NonScraped,
+    BadSector,
+    Finished,
+}
+
+impl RescueStatus {
+    fn from_char(c: &str) -> Option<Self> {
+        match c {
+            "?" => Some(RescueStatus::NonTried),
+            "*" => Some(RescueStatus::NonTrimmed),
+            "/" => Some(RescueStatus::NonScraped),
+            "-" => Some(RescueStatus::BadSector),
+            "+" => Some(RescueStatus::Finished),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            RescueStatus::NonTried => '?',
+            RescueStatus::NonTrimmed => '*',
+            RescueStatus::NonScraped => '/',
+            RescueStatus::BadSector => '-',
+            RescueStatus::Finished => '+',
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RescueBlock {
+    pub start: u64,
+    pub len: u64,
+    pub status: RescueStatus,
+}
+
+/// Contents of a GNU ddrescue mapfile. Only finished blocks hold data read
+/// from the disc, everything else (including what is past the last block) is
+/// considered unrecovered.
+#[derive(Clone, Debug)]
+pub struct RescueMap {
+    pub blocks: Vec<RescueBlock>,
+}
+
+impl RescueMap {
+    pub fn from_blocks(mut blocks: Vec<RescueBlock>) -> Self {
+        blocks.sort_by_key(|b| b.start);
+        RescueMap { blocks }
+    }
+
+    /// Unrecovered areas overlapping `range`, adjacent areas are merged.
+    pub fn bad_ranges_in(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        let mut bad = vec![];
+        let mut current = range.start;
+        let first = self
+            .blocks
+            .partition_point(|b| b.start + b.len <= range.start);
+        for block in &self.blocks[first..] {
+            if current >= range.end {
+                break;
+            }
+            if block.status != RescueStatus::Finished || block.start + block.len <= current {
+                continue;
+            }
+            if block.start > current {
+                bad.push(current..block.start.min(range.end));
+            }
+            current = current.max(block.start + block.len);
+        }
+        if current < range.end {
+            bad.push(current..range.end);
+        }
+        bad
+    }
+
+    pub fn len(&self) -> u64 {
+        self.blocks.last().map_or(0, |b| b.start + b.len)
+    }
+
+    /// End of the rescued area starting at `position`, None if `position` was
+    /// not recovered.
+    fn rescued_end(&self, position: u64) -> Option<u64> {
+        let index = self.blocks.partition_point(|b| b.start + b.len <= position);
+        let mut end = None;
+        for block in &self.blocks[index..] {
+            if block.status != RescueStatus::Finished || block.start > end.unwrap_or(position) {
+                break;
+            }
+            end = Some(block.start + block.len);
+        }
+        end
+    }
+}
+
+pub fn decode_rescue_map_from_file(file: &mut dyn File, path: &Path) -> Result<RescueMap> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut status_found = false;
+    let mut blocks = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+        // The first line holds the current position, status and pass of ddrescue
+        if !status_found {
+            status_found = true;
+            continue;
+        }
+        let block = match tokens[..] {
+            [start, len, status] => parse_number(start)
+                .zip(parse_number(len))
+                .zip(RescueStatus::from_char(status))
+                .map(|((start, len), status)| RescueBlock { start, len, status }),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not parse mapfile ({}), error at line {}: '{}'",
+                path.display(),
+                i + 1,
+                line
+            )
+        })?;
+        blocks.push(block);
+    }
+    if blocks.is_empty() {
+        return Err(anyhow!(
+            "This is not a ddrescue mapfile ({})",
+            path.display()
+        ));
+    }
+    Ok(RescueMap::from_blocks(blocks))
+}
+
+pub fn encode_rescue_map_to_file(file: &mut dyn Write, map: &RescueMap) -> Result<()> {
+    let mut buf = String::new();
+    buf.push_str("# Mapfile. Created by dts-tools\n");
+    buf.push_str("# current_pos  current_status  current_pass\n");
+    buf.push_str(&format!("{:#010x}     +               1\n", map.len()));
+    buf.push_str("#      pos        size  status\n");
+    for b in &map.blocks {
+        buf.push_str(&format!(
+            "{:#010x}  {:#010x}  {}\n",
+            b.start,
+            b.len,
+            b.status.to_char()
+        ));
+    }
+    file.write_all(buf.as_bytes())?;
+    Ok(())
+}
+
+fn parse_number(token: &str) -> Option<u64> {
+    match token.strip_prefix("0x").or(token.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => token.parse().ok(),
+    }
+}
+
+/// Image rescued by ddrescue: reading unrecovered areas fails instead of
+/// returning the zeros (or stale data) stored in the image.
+pub struct RescueFile {
+    file: Box<dyn File>,
+    map: RescueMap,
+    current: u64,
+}
+
+impl RescueFile {
+    pub fn new(file: Box<dyn File>, map: RescueMap) -> Self {
+        RescueFile {
+            file,
+            map,
+            current: 0,
+        }
+    }
+
+    pub fn map(&self) -> &RescueMap {
+        &self.map
+    }
+}
+
+impl File for RescueFile {
+    fn len(&mut self) -> Result<u64> {
+        self.file.len()
+    }
+}
+
+impl Read for RescueFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self
+            .file
+            .len()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if self.current >= len || buf.is_empty() {
+            return Ok(0);
+        }
+        let end = match self.map.rescued_end(self.current) {
+            Some(end) => end,
+            None => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unrecovered sector at offset {:#x}", self.current),
+                ));
+            }
+        };
+        let len = buf.len().min((end - self.current) as usize);
+        let count = self.file.read_buffer_at(&mut buf[..len], self.current)?;
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for RescueFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => {
+                let len = self
+                    .file
+                    .len()
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                len as i64 + from_end
+            }
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memfile::MemoryFile;
+
+    const MAPFILE: &str = "# Mapfile. Created by GNU ddrescue version 1.27
+# current_pos  current_status  current_pass
+0x00001000     +               1
+#      pos        size  status
+0x00000000  0x00000800  +
+0x00000800  0x00000800  -
+0x00001000  0x00000800  +
+0x00001800  0x00000400  ?
+0x00001C00  0x00000400  +
+";
+
+    fn get_map() -> RescueMap {
+        let mut file = MemoryFile::from(MAPFILE.as_bytes().to_vec());
+        decode_rescue_map_from_file(&mut file, Path::new("test.map")).unwrap()
+    }
+
+    #[test]
+    fn test_decode_mapfile() {
+        let map = get_map();
+        assert_eq!(map.blocks.len(), 5);
+        assert_eq!(map.blocks[1].status, RescueStatus::BadSector);
+        assert_eq!(map.len(), 0x2000);
+        assert_eq!(
+            map.bad_ranges_in(0..map.len()),
+            vec![0x800..0x1000, 0x1800..0x1C00]
+        );
+        assert_eq!(
+            map.bad_ranges_in(0x900..0x1900),
+            vec![0x900..0x1000, 0x1800..0x1900]
+        );
+        assert_eq!(map.bad_ranges_in(0x1F00..0x2100), vec![0x2000..0x2100]);
+        assert!(map.bad_ranges_in(0x1000..0x1800).is_empty());
+    }
+
+    #[test]
+    fn test_encode_mapfile() {
+        let map = get_map();
+        let mut encoded = vec![];
+        encode_rescue_map_to_file(&mut encoded, &map).unwrap();
+        let mut file = MemoryFile::from(encoded);
+        let decoded = decode_rescue_map_from_file(&mut file, Path::new("test.map")).unwrap();
+        assert_eq!(decoded.blocks.len(), map.blocks.len());
+        assert_eq!(
+            decoded.bad_ranges_in(0..0x2000),
+            map.bad_ranges_in(0..0x2000)
+        );
+    }
+
+    #[test]
+    fn test_unrecovered_reads_fail() {
+        let data: Vec<u8> = (0..0x2000).map(|i| (i % 251) as u8).collect();
+        let mut file = RescueFile::new(Box::new(MemoryFile::from(data.clone())), get_map());
+
+        assert_eq!(
+            file.read_exact_bytes_at(16, 0x7F0).unwrap(),
+            data[0x7F0..0x800]
+        );
+        assert!(file.read_exact_bytes_at(16, 0x7F8).is_err());
+        assert!(file.read_exact_bytes_at(16, 0x1810).is_err());
+        assert_eq!(
+            file.read_exact_bytes_at(16, 0x1C00).unwrap(),
+            data[0x1C00..0x1C10]
+        );
+    }
+
+    #[test]
+    fn test_read_to_end() {
+        let map = "0x00002000     +               1\n0x00000000  0x00002000  +\n";
+        let map = decode_rescue_map_from_file(
+            &mut MemoryFile::from(map.as_bytes().to_vec()),
+            Path::new("test.map"),
+        )
+        .unwrap();
+        let data: Vec<u8> = (0..0x2000).map(|i| (i % 251) as u8).collect();
+        let mut file = RescueFile::new(Box::new(MemoryFile::from(data.clone())), map);
+
+        let mut read = vec![];
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        file.seek(SeekFrom::Start(0x3000)).unwrap();
+        assert_eq!(file.read(&mut [0; 16]).unwrap(), 0);
+    }
+}
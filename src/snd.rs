@@ -1,4 +1,4 @@
-use std::{path::Path, str};
+use std::{ops::Range, path::Path, str};
 
 use anyhow::{Result, anyhow};
 use encoding_rs::WINDOWS_1252;
@@ -7,13 +7,123 @@ use crate::{
     bcd::{bcd_to_decimal, decimal_to_bcd},
     file::File,
     metadata::{
-        BackupSoundtrackFormat, Offset, Revision, SndFileMetadata, SndType, XDAMetadata, XDMetadata,
-    }, utils::get_title,
+        BackupSoundtrackFormat, OFFSET_FRAMES_PER_SECOND, Offset, Revision, SndFileMetadata,
+        SndType, XDAMetadata, XDMetadata,
+    },
+    utils::get_title,
 };
 
 pub const SND_HEADER_LEN: usize = 92;
 pub const SND_HEADER_LEN_WITH_ENCRYPTION: usize = SND_HEADER_LEN + 3;
 
+/// Bytes of audio per track in each 1/30 s frame, frames hold one block per
+/// track.
+pub const SND_TRACK_BLOCK_LEN: usize = 735;
+
+/// Reel of the trailers (R14T5.AUD), the reels of features are numbered
+/// before it.
+pub const SND_TRAILERS_REEL: u8 = 14;
+/// Found added to the seconds or minutes of some offsets
+const OFFSET_MARKER: u8 = 0x60;
+
+/// How likely (0 to 100) the file is a SND/AUD/AUE file, from the
+/// plausibility of its header and the audio being made of whole frames.
+///
+/// The frames have no known sync word to look for, so the audio is only
+/// checked for its length. The header alone stays below
+/// MIN_CONTENT_CONFIDENCE: files whose audio is not whole frames of the
+/// tracks of the header, or whose length is unknown, are not recognized.
+pub fn get_snd_confidence(file: &mut dyn File) -> u8 {
+    let bytes = match file.read_bytes(SND_HEADER_LEN_WITH_ENCRYPTION) {
+        Ok(b) => b,
+        Err(_) => return 0,
+    };
+    let header: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION] = bytes[..].try_into().unwrap();
+
+    // Fields every header has, checked first so other formats are rejected
+    // without reading the whole file
+    let reel = header[78];
+    let tracks = header[82];
+    if reel == 0
+        || reel > SND_TRAILERS_REEL
+        || tracks == 0
+        || !is_valid_offset(&header[84..88])
+        || !is_valid_offset(&header[88..92])
+    {
+        return 0;
+    }
+
+    let mut confidence = 20;
+    if !matches!(
+        get_optical_backup_format(header[75]),
+        BackupSoundtrackFormat::Unknown(_)
+    ) {
+        confidence += 10;
+    }
+    let revision = Revision::from_header(header);
+    let title_len = match revision {
+        Revision::H1 => 67,
+        Revision::XD => 60,
+        Revision::XDA => 18,
+    };
+    if !matches!(revision, Revision::H1) {
+        confidence += 10;
+    }
+    if is_plausible_title(&header[..title_len]) {
+        confidence += 10;
+    }
+    if header[72..75]
+        .iter()
+        .chain(&header[76..78])
+        .all(|b| *b == 0)
+        && header[79] == 0
+        && header[83] == 0
+    {
+        confidence += 10;
+    }
+
+    let header_len = get_snd_header_len(header);
+    let frame_len = (tracks as usize * SND_TRACK_BLOCK_LEN) as u64;
+    if let Ok(len) = file.len()
+        && len > header_len as u64
+        && (len - header_len as u64).is_multiple_of(frame_len)
+    {
+        confidence += 30;
+    }
+    confidence
+}
+
+/// Length of the header, the audio frames start right after it.
+pub fn get_snd_header_len(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> usize {
+    if is_encrypted_snd_header(bytes) {
+        SND_HEADER_LEN_WITH_ENCRYPTION
+    } else {
+        SND_HEADER_LEN
+    }
+}
+
+pub fn is_encrypted_snd_header(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> bool {
+    !matches!(Revision::from_header(bytes), Revision::H1) && bytes[92] == 1
+}
+
+fn is_valid_offset(bytes: &[u8]) -> bool {
+    match get_offset(bytes) {
+        Ok(Some(o)) => {
+            (o.frames as u64) < OFFSET_FRAMES_PER_SECOND && o.seconds < 60 && o.minutes < 60
+        }
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
+
+fn is_plausible_title(bytes: &[u8]) -> bool {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    end > 0
+        && bytes[..end]
+            .iter()
+            .all(|b| (0x20..0x7F).contains(b) || *b >= 0xA0)
+}
+
 pub fn decode_snd_header_from_file(file: &mut dyn File, path: &Path) -> Result<SndFileMetadata> {
     let size_check = check_snd_size(file);
     if !size_check.0 {
@@ -33,15 +143,25 @@ pub fn decode_snd_header(
     _file: &dyn File,
     bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION],
 ) -> Result<SndFileMetadata> {
+    decode_header_bytes(bytes)
+}
+
+/// Values that can not be decoded are left out, or kept as unknown, with a
+/// warning: one odd byte should not hide the rest of the header.
+fn decode_header_bytes(bytes: &[u8; SND_HEADER_LEN_WITH_ENCRYPTION]) -> Result<SndFileMetadata> {
+    let mut warnings = vec![];
     let revision = Revision::from_header(bytes);
+    if matches!(revision, Revision::XD) && Revision::has_xda_markers(bytes) {
+        warnings.push("Unexpected header layout, partly XDA, decoded as XD".to_string());
+    }
     let (title, xd) = match revision {
         Revision::H1 => {
-            let title = get_title(&bytes[0..67])?;
+            let title = decode_title(&bytes[0..67], &mut warnings);
             (title, None)
         }
         Revision::XD => {
-            let title = get_title(&bytes[0..60])?;
-            let language = get_language(&bytes[60..65])?;
+            let title = decode_title(&bytes[0..60], &mut warnings);
+            let language = get_language(&bytes[60..65], &mut warnings);
             let xd = XDMetadata {
                 language,
                 xda: None,
@@ -49,17 +169,17 @@ pub fn decode_snd_header(
             (title.to_string(), Some(xd))
         }
         Revision::XDA => {
-            let title = get_title(&bytes[0..18])?;
-            let language = get_language(&bytes[60..65])?;
-            let source = get_optional(&bytes[18..31])?;
-            let mix = get_optional(&bytes[31..47])?;
+            let title = decode_title(&bytes[0..18], &mut warnings);
+            let language = get_language(&bytes[60..65], &mut warnings);
+            let source = get_optional(&bytes[18..31], "source", &mut warnings);
+            let mix = get_optional(&bytes[31..47], "mix", &mut warnings);
             let lfe_level = if bytes[50] == b'D' {
-                get_optional(&bytes[47..50])?
+                get_optional(&bytes[47..50], "LFE level", &mut warnings)
             } else {
                 None
             };
-            let surround_delay = get_optional(&bytes[51..55])?;
-            let filters = get_optional(&bytes[55..59])?;
+            let surround_delay = get_optional(&bytes[51..55], "surround delay", &mut warnings);
+            let filters = get_optional(&bytes[55..59], "filters", &mut warnings);
             let xda = XDAMetadata {
                 source,
                 mix,
@@ -75,13 +195,19 @@ pub fn decode_snd_header(
         }
     };
     let title = title.trim_matches(char::from(0)).trim().to_string();
-    let studio = get_studio(&bytes[68..72])?;
-    let optical_backup = get_optical_backup_format(bytes[75])?;
+    let studio = get_studio(&bytes[68..72], &mut warnings);
+    let optical_backup = get_optical_backup_format(bytes[75]);
+    if let BackupSoundtrackFormat::Unknown(value) = optical_backup {
+        warnings.push(format!(
+            "Unknown optical backup soundtrack format: {:#04x}",
+            value
+        ));
+    }
     let id = u16::from_le_bytes([bytes[80], bytes[81]]);
     let tracks = bytes[82];
     let reel = bytes[78];
-    let start_offset = get_offset(&bytes[84..88])?;
-    let end_offset = get_offset(&bytes[88..92])?;
+    let start_offset = decode_offset(&bytes[84..88], "start", &mut warnings);
+    let end_offset = decode_offset(&bytes[88..92], "end", &mut warnings);
     let encryption_key = match revision {
         Revision::XD | Revision::XDA => {
             if bytes[92] == 1 {
@@ -95,7 +221,7 @@ pub fn decode_snd_header(
 
     Ok(SndFileMetadata {
         revision,
-        snd_type: if reel == 14 {
+        snd_type: if reel == SND_TRAILERS_REEL {
             SndType::Trailer
         } else {
             SndType::Feature
@@ -110,58 +236,83 @@ pub fn decode_snd_header(
         start_offset,
         end_offset,
         xd,
+        raw_header: Some(bytes[..get_snd_header_len(bytes)].to_vec()),
+        warnings,
+    })
+}
+
+fn decode_title(bytes: &[u8], warnings: &mut Vec<String>) -> String {
+    get_title(bytes).unwrap_or_else(|_| {
+        warnings
+            .push("Title is neither UTF-8 nor Windows-1252, invalid bytes replaced".to_string());
+        String::from_utf8_lossy(bytes).to_string()
     })
 }
 
-fn get_language(bytes: &[u8]) -> Result<Option<String>> {
+/// Text of a field, invalid UTF-8 is replaced with a warning
+fn decode_text(bytes: &[u8], name: &str, warnings: &mut Vec<String>) -> String {
+    match str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => {
+            warnings.push(format!("Invalid UTF-8 in {}: {:02x?}", name, bytes));
+            String::from_utf8_lossy(bytes).to_string()
+        }
+    }
+}
+
+fn get_language(bytes: &[u8], warnings: &mut Vec<String>) -> Option<String> {
     if bytes[0] == b'*' {
-        let language = str::from_utf8(&bytes[1..])?.trim_matches(char::from(0));
+        let language = decode_text(&bytes[1..], "language", warnings);
+        let language = language.trim_matches(char::from(0));
         if language.is_empty() {
-            Ok(None)
+            None
         } else {
-            Ok(Some(language.to_string()))
+            Some(language.to_string())
         }
     } else {
-        Ok(None)
+        None
     }
 }
 
-fn get_studio(bytes: &[u8]) -> Result<Option<String>> {
-    let studio = str::from_utf8(&bytes)?.trim_matches(char::from(0));
+fn get_studio(bytes: &[u8], warnings: &mut Vec<String>) -> Option<String> {
+    let studio = decode_text(bytes, "studio", warnings);
+    let studio = studio.trim_matches(char::from(0));
     if studio.is_empty() {
-        Ok(None)
+        None
     } else {
-        Ok(Some(studio.to_string()))
+        Some(studio.to_string())
     }
 }
 
-fn get_optional(bytes: &[u8]) -> Result<Option<String>> {
+fn get_optional(bytes: &[u8], name: &str, warnings: &mut Vec<String>) -> Option<String> {
     if bytes[0] == b' ' {
-        let value = str::from_utf8(&bytes[1..])?.trim();
+        let value = decode_text(&bytes[1..], name, warnings);
+        let value = value.trim();
         if value.is_empty() {
-            Ok(None)
+            None
         } else {
-            Ok(Some(value.to_string()))
+            Some(value.to_string())
         }
     } else {
-        Ok(None)
+        None
     }
 }
 
+fn decode_offset(bytes: &[u8], name: &str, warnings: &mut Vec<String>) -> Option<Offset> {
+    get_offset(bytes).unwrap_or_else(|e| {
+        warnings.push(format!("Invalid {} offset {:02x?}: {}", name, bytes, e));
+        None
+    })
+}
+
+/// Offsets are BCD frames, seconds, minutes and hours. Some headers have
+/// OFFSET_MARKER added to the seconds or the minutes, which puts their tens
+/// digit (0 to 5 otherwise) at 6 or more. What it stands for is not known, it
+/// is taken off and offsets are written back without it.
 fn get_offset(bytes: &[u8]) -> Result<Option<Offset>> {
     let frames = bcd_to_decimal(bytes[0])?;
-    let raw_seconds = bytes[1];
-    let seconds = bcd_to_decimal(if raw_seconds > 0x60 {
-        raw_seconds - 0x60
-    } else {
-        raw_seconds
-    })?;
-    let raw_minutes = bytes[2];
-    let minutes = bcd_to_decimal(if raw_minutes > 0x60 {
-        raw_minutes - 0x60
-    } else {
-        raw_minutes
-    })?;
+    let seconds = bcd_to_decimal(remove_offset_marker(bytes[1]))?;
+    let minutes = bcd_to_decimal(remove_offset_marker(bytes[2]))?;
     let hours = bcd_to_decimal(bytes[3])?;
     if frames == 0 && seconds == 0 && minutes == 0 && hours == 0 {
         Ok(None)
@@ -175,7 +326,27 @@ fn get_offset(bytes: &[u8]) -> Result<Option<Offset>> {
     }
 }
 
+/// Seconds and minutes stop at 0x59, anything above 0x60 is marked
+fn remove_offset_marker(value: u8) -> u8 {
+    if value > OFFSET_MARKER {
+        value - OFFSET_MARKER
+    } else {
+        value
+    }
+}
+
+/// Encodes the header of a SND file. A header decoded from a file is encoded
+/// back byte for byte: the bytes of unknown meaning, and the fields that were
+/// not changed, are copied from its `raw_header`.
 pub fn encode_header(data: &SndFileMetadata) -> Result<Vec<u8>> {
+    let mut buffer = encode_fields(data)?;
+    if let Some(raw) = &data.raw_header {
+        restore_raw_bytes(&mut buffer, raw, data)?;
+    }
+    Ok(buffer)
+}
+
+fn encode_fields(data: &SndFileMetadata) -> Result<Vec<u8>> {
     let mut buffer = vec![];
     if let Some(xd) = &data.xd {
         if let Some(xda) = &xd.xda {
@@ -183,11 +354,8 @@ pub fn encode_header(data: &SndFileMetadata) -> Result<Vec<u8>> {
             insert_optional(&mut buffer, &xda.source, b' ', 12);
             insert_optional(&mut buffer, &xda.mix, b' ', 15);
             insert_optional(&mut buffer, &xda.lfe_level, b' ', 2);
-            if xda.lfe_level.is_some() {
-                buffer.push(b'D');
-            } else {
-                buffer.push(b' ');
-            }
+            // Always there, XDA headers are recognized from it
+            buffer.push(b'D');
             insert_optional(&mut buffer, &xda.surround_delay, b' ', 3);
             insert_optional(&mut buffer, &xda.filters, b' ', 3);
             buffer.push(b' ');
@@ -201,28 +369,34 @@ pub fn encode_header(data: &SndFileMetadata) -> Result<Vec<u8>> {
             let zeroes: Vec<u8> = std::iter::repeat_n(0, 5).collect();
             buffer.extend_from_slice(&zeroes);
         }
-        buffer.push(0); // TODO: sometimes other value, investigate why
-        buffer.push(0); // TODO: sometimes other value, investigate why
+        // 65, unknown
+        buffer.push(0);
+        buffer.push(0);
         buffer.push(0);
     } else {
         insert_max(&mut buffer, &data.title.as_bytes(), 0, 67);
+        // 67, unknown
+        buffer.push(0);
     }
 
     // 68
-    insert_optional(&mut buffer, &data.studio, 0, 4);
+    let studio = data.studio.as_deref().unwrap_or_default();
+    insert_max(&mut buffer, studio.as_bytes(), 0, 4);
 
-    // 72
+    // 72, unknown
     buffer.push(0);
     buffer.push(0);
     buffer.push(0);
 
     // 75
-    buffer.push(data.optical_backup as u8);
+    buffer.push(get_optical_backup_value(data.optical_backup));
+    // 76, unknown
     buffer.push(0);
     buffer.push(0);
 
     // 78
     buffer.push(data.reel);
+    // 79, unknown
     buffer.push(0);
 
     // 80
@@ -230,6 +404,7 @@ pub fn encode_header(data: &SndFileMetadata) -> Result<Vec<u8>> {
 
     // 82
     buffer.push(data.tracks);
+    // 83, unknown
     buffer.push(0);
 
     // 84
@@ -244,11 +419,82 @@ pub fn encode_header(data: &SndFileMetadata) -> Result<Vec<u8>> {
     // buffer[90] = 0xA8;
     // buffer[91] = 1;
 
-    // TODO encryption
+    // 92
+    if let Some(key) = data.encryption_key {
+        buffer.push(1);
+        buffer.extend_from_slice(&key.to_le_bytes());
+    }
 
     Ok(buffer)
 }
 
+/// Layout of the header `encode_fields` writes for `data`
+fn get_encoded_revision(data: &SndFileMetadata) -> Revision {
+    match &data.xd {
+        Some(XDMetadata { xda: Some(_), .. }) => Revision::XDA,
+        Some(_) => Revision::XD,
+        None => Revision::H1,
+    }
+}
+
+/// Copies back the bytes of `raw` that are unknown or hold a field `data`
+/// leaves as it was decoded, like padding after a title or offset markers.
+fn restore_raw_bytes(buffer: &mut [u8], raw: &[u8], data: &SndFileMetadata) -> Result<()> {
+    let mut bytes = [0; SND_HEADER_LEN_WITH_ENCRYPTION];
+    let len = raw.len().min(SND_HEADER_LEN_WITH_ENCRYPTION);
+    bytes[..len].copy_from_slice(&raw[..len]);
+    let original = decode_header_bytes(&bytes)?;
+    let mut keep = |range: Range<usize>, unchanged: bool| {
+        if unchanged && range.end <= len && range.end <= buffer.len() {
+            buffer[range.clone()].copy_from_slice(&raw[range]);
+        }
+    };
+
+    keep(68..72, original.studio == data.studio);
+    keep(72..75, true);
+    keep(75..76, original.optical_backup == data.optical_backup);
+    keep(76..78, true);
+    keep(79..80, true);
+    keep(83..84, true);
+    keep(84..88, original.start_offset == data.start_offset);
+    keep(88..92, original.end_offset == data.end_offset);
+
+    let revision = get_encoded_revision(data);
+    if get_encoded_revision(&original) != revision {
+        return Ok(());
+    }
+    let title = original.title == data.title;
+    match (&original.xd, &data.xd) {
+        (Some(a), Some(b)) => {
+            keep(60..65, a.language == b.language);
+            keep(65..68, true);
+            if let (Some(a), Some(b)) = (&a.xda, &b.xda) {
+                keep(0..18, title);
+                keep(18..31, a.source == b.source);
+                keep(31..47, a.mix == b.mix);
+                keep(47..51, a.lfe_level == b.lfe_level);
+                keep(51..55, a.surround_delay == b.surround_delay);
+                keep(55..59, a.filters == b.filters);
+                keep(59..60, true);
+            } else {
+                keep(0..60, title);
+            }
+        }
+        _ => {
+            keep(0..67, title);
+            keep(67..68, true);
+        }
+    }
+    Ok(())
+}
+
+/// Name of the SND and HDR files of a feature reel or a trailer, without
+/// extension. Files are recognized from their header, the name only has to
+/// pair the SND file with its HDR file.
+pub fn get_snd_file_stem(id: u16, reel: u8) -> String {
+    format!("{}_r{}", id, reel)
+}
+
 pub fn get_generic_trailers_header() -> SndFileMetadata {
     SndFileMetadata {
         revision: Revision::XD,
@@ -266,9 +512,34 @@ pub fn get_generic_trailers_header() -> SndFileMetadata {
         }),
         start_offset: None,
         end_offset: None,
+        raw_header: None,
+        warnings: vec![],
     }
 }
 
+/// Header of a feature reel for tests, to adjust before get_test_snd
+#[cfg(test)]
+pub fn get_test_header(id: u16, reel: u8) -> SndFileMetadata {
+    let mut metadata = get_generic_trailers_header();
+    metadata.snd_type = SndType::Feature;
+    metadata.title = "Feature".to_string();
+    metadata.id = id;
+    metadata.reel = reel;
+    metadata
+}
+
+/// Reel for tests: the encoded header followed by `frames` frames, each one
+/// filled with its index
+#[cfg(test)]
+pub fn get_test_snd(metadata: &SndFileMetadata, frames: usize) -> Vec<u8> {
+    let mut data = encode_header(metadata).unwrap();
+    let frame_len = metadata.tracks as usize * SND_TRACK_BLOCK_LEN;
+    for i in 0..frames {
+        data.extend(std::iter::repeat_n(i as u8, frame_len));
+    }
+    data
+}
+
 fn insert_optional(buffer: &mut Vec<u8>, value: &Option<String>, fill: u8, len: usize) {
     if let Some(v) = value {
         buffer.push(b' ');
@@ -284,6 +555,7 @@ fn insert_max(buffer: &mut Vec<u8>, value: &[u8], fill: u8, max: usize) {
     if len >= max {
         buffer.extend_from_slice(&value[0..max]);
     } else {
+        buffer.extend_from_slice(value);
         let fill: Vec<u8> = std::iter::repeat_n(fill, max - len).collect();
         buffer.extend_from_slice(&fill);
     }
@@ -296,6 +568,20 @@ pub fn check_snd_size(file: &mut dyn File) -> (bool, u64) {
     }
 }
 
+/// Replaces the start and end offsets of an encoded header, keeping its other
+/// bytes as they are.
+pub fn set_header_offsets(
+    header: &mut [u8],
+    start_offset: &Option<Offset>,
+    end_offset: &Option<Offset>,
+) -> Result<()> {
+    let mut offsets = vec![];
+    insert_offset(&mut offsets, start_offset)?;
+    insert_offset(&mut offsets, end_offset)?;
+    header[84..92].copy_from_slice(&offsets);
+    Ok(())
+}
+
 fn insert_offset(buffer: &mut Vec<u8>, offset: &Option<Offset>) -> Result<()> {
     if let Some(o) = offset {
         buffer.push(decimal_to_bcd(o.frames)?);
@@ -312,16 +598,192 @@ fn insert_offset(buffer: &mut Vec<u8>, offset: &Option<Offset>) -> Result<()> {
     Ok(())
 }
 
-fn get_optical_backup_format(value: u8) -> Result<BackupSoundtrackFormat> {
+fn get_optical_backup_format(value: u8) -> BackupSoundtrackFormat {
     match value {
-        0x00 => Ok(BackupSoundtrackFormat::DolbyA),
-        0x01 => Ok(BackupSoundtrackFormat::DolbySR),
-        0x02 => Ok(BackupSoundtrackFormat::Academy),
-        0x80 => Ok(BackupSoundtrackFormat::NonSync),
-        0x81 => Ok(BackupSoundtrackFormat::LastReelDolbySR),
-        _ => Err(anyhow!(
-            "Unknown optical backup sountrack format: {:#04x}",
-            value
-        )),
+        0x00 => BackupSoundtrackFormat::DolbyA,
+        0x01 => BackupSoundtrackFormat::DolbySR,
+        0x02 => BackupSoundtrackFormat::Academy,
+        0x80 => BackupSoundtrackFormat::NonSync,
+        0x81 => BackupSoundtrackFormat::LastReelDolbySR,
+        _ => BackupSoundtrackFormat::Unknown(value),
+    }
+}
+
+fn get_optical_backup_value(format: BackupSoundtrackFormat) -> u8 {
+    match format {
+        BackupSoundtrackFormat::DolbyA => 0x00,
+        BackupSoundtrackFormat::DolbySR => 0x01,
+        BackupSoundtrackFormat::Academy => 0x02,
+        BackupSoundtrackFormat::NonSync => 0x80,
+        BackupSoundtrackFormat::LastReelDolbySR => 0x81,
+        BackupSoundtrackFormat::Unknown(value) => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memfile::MemoryFile, probe::MIN_CONTENT_CONFIDENCE};
+
+    fn get_snd(reel: u8, tracks: u8, frames: usize) -> Vec<u8> {
+        let mut metadata = get_test_header(500, reel);
+        metadata.tracks = tracks;
+        get_test_snd(&metadata, frames)
+    }
+
+    #[test]
+    fn test_snd_confidence() {
+        let mut file = MemoryFile::from(get_snd(3, 5, 4));
+        assert!(get_snd_confidence(&mut file) >= MIN_CONTENT_CONFIDENCE);
+
+        // Truncated audio
+        let mut data = get_snd(3, 5, 4);
+        data.truncate(data.len() - 100);
+        let mut file = MemoryFile::from(data);
+        assert!(get_snd_confidence(&mut file) < MIN_CONTENT_CONFIDENCE);
+
+        // Complete XD header without audio
+        let mut file = MemoryFile::from(get_test_snd(&get_test_header(500, 3), 0));
+        assert!(get_snd_confidence(&mut file) < MIN_CONTENT_CONFIDENCE);
+        let mut file = MemoryFile::from(get_test_snd(&get_test_header(500, 3), 4));
+        assert!(get_snd_confidence(&mut file) >= MIN_CONTENT_CONFIDENCE);
+
+        // Invalid reel, invalid BCD offset
+        let mut file = MemoryFile::from(get_snd(20, 5, 4));
+        assert_eq!(get_snd_confidence(&mut file), 0);
+        let mut data = get_snd(3, 5, 4);
+        data[85] = 0x7A;
+        let mut file = MemoryFile::from(data);
+        assert_eq!(get_snd_confidence(&mut file), 0);
+    }
+
+    /// Headers of each revision with the unknown bytes set, padding after the
+    /// titles and offset markers
+    fn get_sample_headers() -> Vec<[u8; SND_HEADER_LEN_WITH_ENCRYPTION]> {
+        let mut h1 = [0; SND_HEADER_LEN_WITH_ENCRYPTION];
+        h1[0..67].fill(b' ');
+        h1[0..7].copy_from_slice(b"FEATURE");
+        h1[67] = 0x12;
+
+        let mut xd = [0; SND_HEADER_LEN_WITH_ENCRYPTION];
+        xd[0..5].copy_from_slice(b"CAF\xC9\0");
+        xd[10..14].copy_from_slice(b"junk");
+        xd[60..64].copy_from_slice(b"*ENG");
+        xd[65..68].copy_from_slice(&[0x20, 0x01, 0x00]);
+        xd[92..95].copy_from_slice(&[1, 0x34, 0x12]);
+
+        let mut xda = [b' '; SND_HEADER_LEN_WITH_ENCRYPTION];
+        xda[0..10].copy_from_slice(b"MY FEATURE");
+        xda[19..26].copy_from_slice(b"DIGITAL");
+        xda[32..35].copy_from_slice(b"5.1");
+        xda[48..50].copy_from_slice(b"10");
+        xda[50] = b'D';
+        xda[52..54].copy_from_slice(b"20");
+        xda[56..58].copy_from_slice(b"SR");
+        xda[60..68].copy_from_slice(b"*FR\0\0\0\0\x09");
+
+        let mut headers = vec![h1, xd, xda];
+        for h in &mut headers {
+            h[68..72].copy_from_slice(b"WB\0\0");
+            h[72..75].copy_from_slice(&[1, 2, 3]);
+            h[75] = 0x81;
+            h[76..78].copy_from_slice(&[4, 5]);
+            h[78] = 3;
+            h[79] = 6;
+            h[80..82].copy_from_slice(&1234u16.to_le_bytes());
+            h[82] = 6;
+            h[83] = 7;
+            h[84..88].copy_from_slice(&[0x00, 0x61, 0x00, 0x00]);
+            h[88..92].copy_from_slice(&[0x15, 0x30, 0x79, 0x00]);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let revisions = [Revision::H1, Revision::XD, Revision::XDA];
+        for (bytes, revision) in get_sample_headers().iter().zip(revisions) {
+            let data = decode_header_bytes(bytes).unwrap();
+            assert!(data.revision == revision);
+            let len = get_snd_header_len(bytes);
+            assert_eq!(encode_header(&data).unwrap(), &bytes[..len]);
+        }
+    }
+
+    #[test]
+    fn test_unknown_header_values() {
+        let mut bytes = get_sample_headers()[0];
+        bytes[75] = 0x42;
+        bytes[85] = 0x9A;
+        bytes[68..72].copy_from_slice(&[0xFF, 0, 0, 0]);
+        let data = decode_header_bytes(&bytes).unwrap();
+        assert!(data.optical_backup == BackupSoundtrackFormat::Unknown(0x42));
+        assert_eq!(data.start_offset, None);
+        assert_eq!(data.studio.as_deref(), Some("\u{FFFD}"));
+        assert_eq!(data.warnings.len(), 3);
+        assert_eq!(data.id, 1234);
+        assert_eq!(encode_header(&data).unwrap(), &bytes[..SND_HEADER_LEN]);
+
+        // Nearly XDA
+        let mut bytes = get_sample_headers()[2];
+        bytes[50] = b' ';
+        let data = decode_header_bytes(&bytes).unwrap();
+        assert!(data.revision == Revision::XD);
+        assert_eq!(data.warnings.len(), 1);
+        assert_eq!(encode_header(&data).unwrap(), &bytes[..SND_HEADER_LEN]);
+
+        let mut data = get_snd(3, 5, 4);
+        data[75] = 0x42;
+        let mut file = MemoryFile::from(data);
+        assert!(get_snd_confidence(&mut file) >= MIN_CONTENT_CONFIDENCE);
+    }
+
+    #[test]
+    fn test_header_changed_fields() {
+        let bytes = get_sample_headers()[1];
+        let mut data = decode_header_bytes(&bytes).unwrap();
+        data.title = "NEW".to_string();
+        data.end_offset = Some(Offset::from_frames(90));
+        data.encryption_key = None;
+        let encoded = encode_header(&data).unwrap();
+        assert_eq!(encoded.len(), SND_HEADER_LEN);
+        assert_eq!(&encoded[0..4], b"NEW\0");
+        assert!(encoded[4..60].iter().all(|b| *b == 0));
+        assert_eq!(&encoded[60..88], &bytes[60..88]);
+        assert_eq!(&encoded[88..92], &[0x00, 0x03, 0x00, 0x00]);
+
+        let mut array = [0; SND_HEADER_LEN_WITH_ENCRYPTION];
+        array[..SND_HEADER_LEN].copy_from_slice(&encoded);
+        let decoded = decode_header_bytes(&array).unwrap();
+        assert_eq!(decoded.title, "NEW");
+        assert_eq!(decoded.start_offset, data.start_offset);
+        assert_eq!(decoded.encryption_key, None);
+    }
+
+    #[test]
+    fn test_new_header() {
+        for bytes in get_sample_headers() {
+            let mut data = decode_header_bytes(&bytes).unwrap();
+            data.raw_header = None;
+            let encoded = encode_header(&data).unwrap();
+            assert_eq!(encoded.len(), get_snd_header_len(&bytes));
+            let mut array = [0; SND_HEADER_LEN_WITH_ENCRYPTION];
+            array[..encoded.len()].copy_from_slice(&encoded);
+            let decoded = decode_header_bytes(&array).unwrap();
+            assert!(decoded.revision == data.revision);
+            assert_eq!(decoded.title, data.title);
+            assert_eq!(decoded.studio, data.studio);
+            assert_eq!(decoded.id, data.id);
+            assert_eq!(decoded.reel, data.reel);
+            assert_eq!(decoded.start_offset, data.start_offset);
+            assert_eq!(decoded.end_offset, data.end_offset);
+            assert_eq!(decoded.encryption_key, data.encryption_key);
+            let mix = |m: &SndFileMetadata| {
+                m.xd.as_ref()
+                    .and_then(|xd| xd.xda.as_ref())
+                    .and_then(|x| x.mix.clone())
+            };
+            assert_eq!(mix(&decoded), mix(&data));
+        }
     }
 }
@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Result;
+
+use crate::file::File;
+
+pub const STDIN_PATH: &str = "-";
+
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+const READ_CHUNK_LEN: usize = 64 * 1024;
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub fn is_stdin_path(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+}
+
+enum StreamBuffer {
+    Memory(Vec<u8>),
+    Spill(SpillFile),
+}
+
+struct SpillFile {
+    file: fs::File,
+    path: PathBuf,
+    len: u64,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Seekable view over a non-seekable stream (stdin, pipes).
+///
+/// The stream is only read as far as needed to serve the requested bytes, and
+/// what was read is kept so the data can be read again. Data stays in memory
+/// up to `limit` bytes, then everything is moved to a temporary file.
+pub struct StreamFile {
+    reader: Box<dyn Read>,
+    buffer: StreamBuffer,
+    limit: usize,
+    eof: bool,
+    current: u64,
+}
+
+impl StreamFile {
+    pub fn from_stdin() -> Self {
+        Self::new(Box::new(std::io::stdin()), MEMORY_LIMIT)
+    }
+
+    pub fn new(reader: Box<dyn Read>, limit: usize) -> Self {
+        StreamFile {
+            reader,
+            buffer: StreamBuffer::Memory(vec![]),
+            limit,
+            eof: false,
+            current: 0,
+        }
+    }
+
+    /// Number of bytes read from the stream so far.
+    pub fn buffered_len(&self) -> u64 {
+        match &self.buffer {
+            StreamBuffer::Memory(data) => data.len() as u64,
+            StreamBuffer::Spill(spill) => spill.len,
+        }
+    }
+
+    fn fill_to(&mut self, position: u64) -> std::io::Result<()> {
+        let mut chunk = vec![0; READ_CHUNK_LEN];
+        while !self.eof && self.buffered_len() < position {
+            let count = match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(count) => count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.append(&chunk[..count])?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if let StreamBuffer::Memory(data) = &mut self.buffer {
+            if data.len() + bytes.len() <= self.limit {
+                data.extend_from_slice(bytes);
+                return Ok(());
+            }
+            let mut spill = create_spill_file()?;
+            spill.file.write_all(data)?;
+            spill.len = data.len() as u64;
+            self.buffer = StreamBuffer::Spill(spill);
+        }
+        if let StreamBuffer::Spill(spill) = &mut self.buffer {
+            spill.file.seek(SeekFrom::End(0))?;
+            spill.file.write_all(bytes)?;
+            spill.len += bytes.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl File for StreamFile {
+    fn len(&mut self) -> Result<u64> {
+        self.fill_to(u64::MAX)?;
+        Ok(self.buffered_len())
+    }
+}
+
+impl Read for StreamFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.fill_to(self.current + buf.len() as u64)?;
+        let len = self.buffered_len();
+        if self.current >= len {
+            return Ok(0);
+        }
+        let count = buf.len().min((len - self.current) as usize);
+        match &mut self.buffer {
+            StreamBuffer::Memory(data) => {
+                let start = self.current as usize;
+                buf[..count].copy_from_slice(&data[start..start + count]);
+            }
+            StreamBuffer::Spill(spill) => {
+                spill.file.seek(SeekFrom::Start(self.current))?;
+                spill.file.read_exact(&mut buf[..count])?;
+            }
+        }
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for StreamFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => {
+                self.fill_to(u64::MAX)?;
+                self.buffered_len() as i64 + from_end
+            }
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+fn create_spill_file() -> std::io::Result<SpillFile> {
+    let path = std::env::temp_dir().join(format!(
+        "dts-tools-{}-{}.tmp",
+        process::id(),
+        SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    Ok(SpillFile { file, path, len: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn get_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 253) as u8).collect()
+    }
+
+    #[test]
+    fn test_reads_only_what_is_needed() {
+        let data = get_data(READ_CHUNK_LEN * 8);
+        let mut file = StreamFile::new(Box::new(Cursor::new(data.clone())), MEMORY_LIMIT);
+
+        assert_eq!(file.read_bytes(92).unwrap(), data[..92]);
+        assert_eq!(file.buffered_len(), READ_CHUNK_LEN as u64);
+
+        assert_eq!(file.len().unwrap(), data.len() as u64);
+        assert_eq!(file.read_exact_bytes_at(10, 100).unwrap(), data[100..110]);
+    }
+
+    #[test]
+    fn test_spill_to_temporary_file() {
+        let data = get_data(READ_CHUNK_LEN * 4 + 17);
+        let mut file = StreamFile::new(Box::new(Cursor::new(data.clone())), READ_CHUNK_LEN);
+
+        let at = READ_CHUNK_LEN as u64 * 3 - 5;
+        assert_eq!(
+            file.read_exact_bytes_at(10, at).unwrap(),
+            data[at as usize..at as usize + 10]
+        );
+        assert!(matches!(file.buffer, StreamBuffer::Spill(_)));
+
+        let mut all = vec![];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    fmt,
+    ops::{Add, Sub},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+
+/// 35 mm film, 4 perforations per frame
+pub const FILM_FRAMES_PER_FOOT: u64 = 16;
+/// Projection speed of 35 mm film, footage is counted at this rate
+pub const FILM_FRAME_RATE: FrameRate = FrameRate::Fps24;
+
+/// Frames of 29.97 drop-frame timecode in ten minutes, and in each of the
+/// nine minutes dropping two frame numbers
+const DROP_FRAMES_PER_10_MINUTES: u64 = 17982;
+const DROP_FRAMES_PER_MINUTE: u64 = 1798;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 30000/1001 frames per second, frame numbers 0 and 1 are skipped at the
+    /// start of every minute but every tenth
+    Fps2997DropFrame,
+    Fps30,
+}
+
+impl FrameRate {
+    /// Frame numbers in a second of timecode
+    pub const fn nominal(self) -> u64 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997DropFrame | FrameRate::Fps30 => 30,
+        }
+    }
+
+    pub fn is_drop_frame(self) -> bool {
+        matches!(self, FrameRate::Fps2997DropFrame)
+    }
+
+    /// Frames per second as a fraction
+    fn ratio(self) -> (u64, u64) {
+        match self {
+            FrameRate::Fps2997DropFrame => (30000, 1001),
+            _ => (self.nominal(), 1),
+        }
+    }
+
+    pub fn fps(self) -> f64 {
+        let (numerator, denominator) = self.ratio();
+        numerator as f64 / denominator as f64
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameRate::Fps24 => write!(f, "24"),
+            FrameRate::Fps25 => write!(f, "25"),
+            FrameRate::Fps2997DropFrame => write!(f, "29.97 DF"),
+            FrameRate::Fps30 => write!(f, "30"),
+        }
+    }
+}
+
+/// Parses "24", "25", "29.97" (always drop-frame, "29.97df" also works) and
+/// "30".
+impl FromStr for FrameRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        match normalized.as_str() {
+            "24" => Ok(FrameRate::Fps24),
+            "25" => Ok(FrameRate::Fps25),
+            "29.97" | "29.97df" => Ok(FrameRate::Fps2997DropFrame),
+            "30" => Ok(FrameRate::Fps30),
+            _ => Err(anyhow!(
+                "Invalid frame rate '{}', expected 24, 25, 29.97 or 30",
+                s
+            )),
+        }
+    }
+}
+
+/// A position or a duration, counted in frames at a given rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timecode {
+    count: u64,
+    rate: FrameRate,
+}
+
+impl Timecode {
+    pub fn new(count: u64, rate: FrameRate) -> Self {
+        Timecode { count, rate }
+    }
+
+    pub fn from_hmsf(
+        hours: u64,
+        minutes: u64,
+        seconds: u64,
+        frames: u64,
+        rate: FrameRate,
+    ) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid timecode {:02}:{:02}:{:02}:{:02} at {} fps",
+                hours,
+                minutes,
+                seconds,
+                frames,
+                rate
+            )
+        };
+        if minutes >= 60 || seconds >= 60 || frames >= rate.nominal() {
+            return Err(invalid());
+        }
+        let total_minutes = hours
+            .checked_mul(60)
+            .and_then(|m| m.checked_add(minutes))
+            .ok_or_else(invalid)?;
+        let mut count = total_minutes
+            .checked_mul(60)
+            .and_then(|s| s.checked_add(seconds))
+            .and_then(|s| s.checked_mul(rate.nominal()))
+            .and_then(|f| f.checked_add(frames))
+            .ok_or_else(invalid)?;
+        if rate.is_drop_frame() {
+            if seconds == 0 && frames < 2 && !minutes.is_multiple_of(10) {
+                return Err(invalid());
+            }
+            count -= 2 * (total_minutes - total_minutes / 10);
+        }
+        Ok(Timecode { count, rate })
+    }
+
+    /// Hours, minutes, seconds and frames as labelled at the rate
+    pub fn to_hmsf(self) -> (u64, u64, u64, u64) {
+        let mut labelled = self.count;
+        if self.rate.is_drop_frame() {
+            let tens = self.count / DROP_FRAMES_PER_10_MINUTES;
+            let rest = self.count % DROP_FRAMES_PER_10_MINUTES;
+            labelled += 18 * tens;
+            if rest >= 2 {
+                labelled += 2 * ((rest - 2) / DROP_FRAMES_PER_MINUTE);
+            }
+        }
+        let nominal = self.rate.nominal();
+        let seconds = labelled / nominal;
+        (
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            labelled % nominal,
+        )
+    }
+
+    pub fn from_seconds(seconds: f64, rate: FrameRate) -> Self {
+        Timecode {
+            count: (seconds * rate.fps()).round().max(0.0) as u64,
+            rate,
+        }
+    }
+
+    /// Parses "HH:MM:SS:FF" ("HH:MM:SS;FF" at 29.97 drop-frame) or
+    /// "H:MM:SS.mmm", the fraction of a second being optional and rounded to
+    /// the nearest frame.
+    pub fn parse(s: &str, rate: FrameRate) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid timecode '{}', expected HH:MM:SS:FF or H:MM:SS.mmm",
+                s
+            )
+        };
+        let (time, frames) = match s.rsplit_once(';') {
+            Some((time, frames)) if rate.is_drop_frame() => (time, Some(frames)),
+            Some(_) => {
+                return Err(anyhow!(
+                    "Invalid timecode '{}', ';' is only used at 29.97 drop-frame",
+                    s
+                ));
+            }
+            None => match s.matches(':').count() {
+                3 => s.rsplit_once(':').map(|(t, f)| (t, Some(f))).unwrap(),
+                _ => (s, None),
+            },
+        };
+        let (time, fraction) = match frames {
+            Some(_) => (time, ""),
+            None => time.split_once('.').unwrap_or((time, "")),
+        };
+        let parse = |p: &str| {
+            if p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()) {
+                Err(invalid())
+            } else {
+                p.parse::<u64>().map_err(|_| invalid())
+            }
+        };
+        let parts: Vec<u64> = time.split(':').map(parse).collect::<Result<_>>()?;
+        let [hours, minutes, seconds] = parts[..] else {
+            return Err(invalid());
+        };
+        if let Some(frames) = frames {
+            return Timecode::from_hmsf(hours, minutes, seconds, parse(frames)?, rate);
+        }
+        if minutes >= 60 || seconds >= 60 {
+            return Err(invalid());
+        }
+        let seconds = hours
+            .checked_mul(3600)
+            .and_then(|s| s.checked_add(minutes * 60 + seconds))
+            .ok_or_else(invalid)?;
+        let fraction = if fraction.is_empty() {
+            0.0
+        } else {
+            parse(fraction)?;
+            format!("0.{}", fraction)
+                .parse::<f64>()
+                .map_err(|_| invalid())?
+        };
+        Ok(Timecode::from_seconds(seconds as f64 + fraction, rate))
+    }
+
+    pub fn count(self) -> u64 {
+        self.count
+    }
+
+    /// The same time at another rate, to the nearest frame
+    pub fn to_rate(self, rate: FrameRate) -> Timecode {
+        let (from_numerator, from_denominator) = self.rate.ratio();
+        let (to_numerator, to_denominator) = rate.ratio();
+        let numerator = self.count as u128 * (to_numerator * from_denominator) as u128;
+        let denominator = (to_denominator * from_numerator) as u128;
+        Timecode {
+            count: ((numerator + denominator / 2) / denominator) as u64,
+            rate,
+        }
+    }
+
+    /// Length of 35 mm film running for this duration
+    pub fn to_feet_frames(self) -> FeetFrames {
+        let count = self.to_rate(FILM_FRAME_RATE).count;
+        FeetFrames {
+            feet: count / FILM_FRAMES_PER_FOOT,
+            frames: count % FILM_FRAMES_PER_FOOT,
+        }
+    }
+
+    pub fn from_feet_frames(footage: FeetFrames, rate: FrameRate) -> Timecode {
+        Timecode::new(footage.to_frames(), FILM_FRAME_RATE).to_rate(rate)
+    }
+
+    pub fn checked_sub(self, other: Timecode) -> Option<Timecode> {
+        let other = other.to_rate(self.rate);
+        self.count.checked_sub(other.count).map(|count| Timecode {
+            count,
+            rate: self.rate,
+        })
+    }
+
+    /// "H:MM:SS.mmm", whatever the rate, the milliseconds truncated
+    pub fn to_clock_string(self) -> String {
+        let (numerator, denominator) = self.rate.ratio();
+        let millis = (self.count as u128 * 1000 * denominator as u128 / numerator as u128) as u64;
+        format!(
+            "{}:{:02}:{:02}.{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+/// The other timecode is brought to the rate of the first one
+impl Add for Timecode {
+    type Output = Timecode;
+
+    fn add(self, other: Timecode) -> Timecode {
+        Timecode {
+            count: self.count + other.to_rate(self.rate).count,
+            rate: self.rate,
+        }
+    }
+}
+
+/// The other timecode is brought to the rate of the first one, the
+/// difference stops at 0 (see `checked_sub`)
+impl Sub for Timecode {
+    type Output = Timecode;
+
+    fn sub(self, other: Timecode) -> Timecode {
+        self.checked_sub(other).unwrap_or(Timecode {
+            count: 0,
+            rate: self.rate,
+        })
+    }
+}
+
+/// "HH:MM:SS:FF", "HH:MM:SS;FF" at 29.97 drop-frame
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, minutes, seconds, frames) = self.to_hmsf();
+        let separator = if self.rate.is_drop_frame() { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            hours, minutes, seconds, separator, frames
+        )
+    }
+}
+
+/// Parses a timecode as accepted by `Timecode::parse`, or a length of 35 mm
+/// film as "FEET+FF", followed by an optional "@RATE". Without one the rate is
+/// 29.97 drop-frame for "HH:MM:SS;FF", 30 (the DTS rate) otherwise.
+impl FromStr for Timecode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (timecode, rate) = match s.split_once('@') {
+            Some((timecode, rate)) => (timecode.trim(), rate.parse()?),
+            None if s.contains(';') => (s, FrameRate::Fps2997DropFrame),
+            None => (s, FrameRate::Fps30),
+        };
+        if timecode.contains('+') {
+            Ok(Timecode::from_feet_frames(timecode.parse()?, rate))
+        } else {
+            Timecode::parse(timecode, rate)
+        }
+    }
+}
+
+/// Length of 35 mm film
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeetFrames {
+    pub feet: u64,
+    pub frames: u64,
+}
+
+impl FeetFrames {
+    pub fn to_frames(self) -> u64 {
+        self.feet * FILM_FRAMES_PER_FOOT + self.frames
+    }
+}
+
+/// "FEET+FF"
+impl fmt::Display for FeetFrames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:02}", self.feet, self.frames)
+    }
+}
+
+impl FromStr for FeetFrames {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("Invalid footage '{}', expected FEET+FF", s);
+        let (feet, frames) = s.split_once('+').unwrap_or((s, "0"));
+        let feet = feet.trim().parse::<u64>().map_err(|_| invalid())?;
+        let frames = frames.trim().parse::<u64>().map_err(|_| invalid())?;
+        if frames >= FILM_FRAMES_PER_FOOT || feet.checked_mul(FILM_FRAMES_PER_FOOT).is_none() {
+            return Err(invalid());
+        }
+        Ok(FeetFrames { feet, frames })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_frame() {
+        let rate = FrameRate::Fps2997DropFrame;
+        for (count, displayed) in [
+            (0, "00:00:00;00"),
+            (1799, "00:00:59;29"),
+            (1800, "00:01:00;02"),
+            (17981, "00:09:59;29"),
+            (17982, "00:10:00;00"),
+            (107892, "01:00:00;00"),
+        ] {
+            let timecode = Timecode::new(count, rate);
+            assert_eq!(timecode.to_string(), displayed);
+            assert_eq!(displayed.parse::<Timecode>().unwrap(), timecode);
+        }
+        assert!(Timecode::parse("00:01:00;00", rate).is_err());
+        assert!(Timecode::parse("00:10:00;00", rate).is_ok());
+        for count in 0..40000 {
+            let (hours, minutes, seconds, frames) = Timecode::new(count, rate).to_hmsf();
+            let timecode = Timecode::from_hmsf(hours, minutes, seconds, frames, rate).unwrap();
+            assert_eq!(timecode.count(), count);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let timecode: Timecode = "01:02:03:04@24".parse().unwrap();
+        assert_eq!(timecode.count(), (3600 + 2 * 60 + 3) * 24 + 4);
+        assert_eq!(timecode.to_string(), "01:02:03:04");
+        assert_eq!(
+            "0:00:01.5".parse::<Timecode>().unwrap(),
+            Timecode::new(45, FrameRate::Fps30)
+        );
+        assert_eq!("0:00:01.5 @ 25".parse::<Timecode>().unwrap().count(), 38);
+        assert!("00:00:00:24@24".parse::<Timecode>().is_err());
+        assert!("00:00:00;10@30".parse::<Timecode>().is_err());
+        assert!("0:60:00".parse::<Timecode>().is_err());
+        assert!("0:00:00.-5".parse::<Timecode>().is_err());
+        // Hours overflowing the frame count
+        assert!("99999999999999999:00:00:00".parse::<Timecode>().is_err());
+        assert!("99999999999999999:00:00.5".parse::<Timecode>().is_err());
+        assert!("9999999999999999999+00".parse::<Timecode>().is_err());
+    }
+
+    #[test]
+    fn test_clock_string() {
+        // Milliseconds are truncated, like the header offsets always showed
+        assert_eq!(
+            Timecode::new(1, FrameRate::Fps30).to_clock_string(),
+            "0:00:00.033"
+        );
+        assert_eq!(
+            Timecode::new(2, FrameRate::Fps30).to_clock_string(),
+            "0:00:00.066"
+        );
+        assert_eq!(
+            Timecode::new(30000, FrameRate::Fps2997DropFrame).to_clock_string(),
+            "0:16:41.000"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let start = Timecode::new(300, FrameRate::Fps30);
+        let end = Timecode::new(30 * 60, FrameRate::Fps30);
+        assert_eq!((end - start).to_clock_string(), "0:00:50.000");
+        assert_eq!((start - end).count(), 0);
+        assert_eq!(start.checked_sub(end), None);
+        // 1 s at 24 fps added to 10 s at 30 fps
+        let sum = start + Timecode::new(24, FrameRate::Fps24);
+        assert_eq!(sum, Timecode::new(330, FrameRate::Fps30));
+        assert_eq!(
+            Timecode::new(30, FrameRate::Fps30).to_rate(FrameRate::Fps2997DropFrame),
+            Timecode::new(30, FrameRate::Fps2997DropFrame)
+        );
+    }
+
+    #[test]
+    fn test_feet_frames() {
+        // 90 feet a minute at 24 fps
+        let minute = Timecode::new(60 * 30, FrameRate::Fps30);
+        assert_eq!(
+            minute.to_feet_frames(),
+            FeetFrames {
+                feet: 90,
+                frames: 0
+            }
+        );
+        let footage: FeetFrames = "1234+05".parse().unwrap();
+        assert_eq!(footage.to_string(), "1234+05");
+        let timecode = Timecode::from_feet_frames(footage, FrameRate::Fps24);
+        assert_eq!(timecode.count(), 1234 * 16 + 5);
+        assert_eq!(timecode.to_feet_frames(), footage);
+        assert!("12+16".parse::<FeetFrames>().is_err());
+        assert_eq!(
+            "90+00".parse::<Timecode>().unwrap(),
+            Timecode::new(60 * 30, FrameRate::Fps30)
+        );
+    }
+}
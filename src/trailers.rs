@@ -1,5 +1,4 @@
 use std::{
-    fs,
     io::{BufRead, BufReader, Lines, Write},
     path::Path,
 };
@@ -11,6 +10,9 @@ use crate::{
     metadata::{TrailersMetadata, TrailersMetadataTxtEntry},
 };
 
+/// Length of the units used by the START and END columns of R14TRLR.TXT.
+pub const TRAILER_UNIT_LEN: usize = 3675;
+
 pub fn decode_trailers_from_txt_file(file: &mut dyn File, path: &Path) -> Result<TrailersMetadata> {
     let lines = read_lines(file);
     let mut entries = vec![];
@@ -28,7 +30,7 @@ pub fn decode_trailers_from_txt_file(file: &mut dyn File, path: &Path) -> Result
     Ok(TrailersMetadata { entries })
 }
 
-pub fn encode_trailers_to_txt_file(file: &mut fs::File, data: &TrailersMetadata) -> Result<()> {
+pub fn encode_trailers_to_txt_file(file: &mut dyn Write, data: &TrailersMetadata) -> Result<()> {
     let mut buf: Vec<u8> = vec![];
     buf.extend_from_slice(get_header().as_bytes());
     for e in &data.entries {
@@ -0,0 +1,490 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    binfile::BinFile,
+    detect::{FileType as DetectedFileType, get_file_type},
+    file::{DirEntry, File, FileSystem, FileType},
+    hdd::decode_hdd_img_from_file,
+    httpfile::{HttpFile, is_http_url},
+    isofile::IsoFileSystem,
+    osfile::{OsFile, OsFileSystem, is_device_path},
+    snd::decode_snd_header_from_file,
+    vdisk::decode_virtual_disk_from_file,
+};
+
+const CD_DTS_DIR_NAME: &str = "dts";
+const CD_DTS_EXE_NAME: &str = "dts.exe";
+const HDD_DATA_DIR: &str = "/data";
+
+trait SourceFileSystem {
+    fn is_source_file(&mut self, path: &Path) -> bool;
+    fn is_source_dir(&mut self, path: &Path) -> bool;
+    fn open_source_file(&mut self, path: &Path) -> Result<Box<dyn File>>;
+    fn read_source_dir(&mut self, path: &Path) -> Result<Vec<(PathBuf, FileType)>>;
+}
+
+impl<FS: FileSystem> SourceFileSystem for FS
+where
+    <FS as FileSystem>::File: 'static,
+{
+    fn is_source_file(&mut self, path: &Path) -> bool {
+        FileSystem::is_file(self, path)
+    }
+
+    fn is_source_dir(&mut self, path: &Path) -> bool {
+        FileSystem::is_dir(self, path)
+    }
+
+    fn open_source_file(&mut self, path: &Path) -> Result<Box<dyn File>> {
+        Ok(Box::new(FileSystem::open_file(self, path)?))
+    }
+
+    fn read_source_dir(&mut self, path: &Path) -> Result<Vec<(PathBuf, FileType)>> {
+        FileSystem::read_dir(self, path)?
+            .iter()
+            .map(|e| Ok((e.path()?, e.file_type()?)))
+            .collect()
+    }
+}
+
+struct UnionSource {
+    name: String,
+    fs: Box<dyn SourceFileSystem>,
+    root: PathBuf,
+}
+
+/// Several inputs (directories, ISO files, HDD images) seen as one library.
+///
+/// The root of the union lists the library directory of every source. Each
+/// entry is exposed under `/<source index>/<path relative to the source root>`
+/// so identically named files from different sources don't collide.
+pub struct UnionFileSystem {
+    sources: Vec<UnionSource>,
+}
+
+pub struct UnionConflict {
+    pub id: u16,
+    pub reel: u8,
+    pub paths: Vec<PathBuf>,
+}
+
+impl UnionFileSystem {
+    pub fn new() -> Self {
+        UnionFileSystem { sources: vec![] }
+    }
+
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P], verbose: bool) -> Result<Self> {
+        let mut union = UnionFileSystem::new();
+        for path in paths {
+            add_source_from_path(&mut union, path.as_ref(), verbose)?;
+        }
+        Ok(union)
+    }
+
+    pub fn add_source<FS: FileSystem + 'static, P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        fs: FS,
+        root: P,
+    ) where
+        <FS as FileSystem>::File: 'static,
+    {
+        self.sources.push(UnionSource {
+            name: name.to_string(),
+            fs: Box::new(fs),
+            root: root.as_ref().to_path_buf(),
+        });
+    }
+
+    pub fn source_names(&self) -> Vec<(PathBuf, &str)> {
+        self.sources
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (get_source_path(i), s.name.as_str()))
+            .collect()
+    }
+
+    /// Finds every id and reel pair present in more than one source,
+    /// looking into subdirectories.
+    pub fn find_conflicts(&mut self) -> Result<Vec<UnionConflict>> {
+        let mut found: BTreeMap<(u16, u8), Vec<(usize, PathBuf)>> = BTreeMap::new();
+        for path in self.find_audio_files()? {
+            let (index, _) = self.get_source_and_path(&path)?;
+            let mut file = self.open_file(&path)?;
+            match decode_snd_header_from_file(&mut file, &path) {
+                Ok(metadata) => found
+                    .entry((metadata.id, metadata.reel))
+                    .or_default()
+                    .push((index, path)),
+                Err(e) => println!("Warning: {}", e),
+            }
+        }
+
+        Ok(found
+            .into_iter()
+            .filter(|(_, paths)| paths.iter().any(|(i, _)| *i != paths[0].0))
+            .map(|((id, reel), paths)| UnionConflict {
+                id,
+                reel,
+                paths: paths.into_iter().map(|(_, p)| p).collect(),
+            })
+            .collect())
+    }
+
+    /// Every SND/AUD/AUE file of the library, looking into subdirectories.
+    pub fn find_audio_files(&mut self) -> Result<Vec<PathBuf>> {
+        let mut found = vec![];
+        let mut dirs = vec![PathBuf::from("/")];
+        while let Some(dir) = dirs.pop() {
+            for e in self.read_dir(&dir)? {
+                // Records for the current and parent directories of ISO files
+                if e.file_name().chars().all(|c| c < ' ') {
+                    continue;
+                }
+                let path = e.path()?;
+                match e.file_type()? {
+                    FileType::Directory => dirs.push(path),
+                    FileType::File if is_audio_file(&path) => found.push(path),
+                    FileType::File => {}
+                }
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+
+    /// Path of a union entry, shown with the name of its source instead of
+    /// the source index.
+    pub fn get_display_path(&self, path: &Path) -> String {
+        match self.get_source_and_path(path) {
+            Ok((i, p)) => format!("{}: {}", self.sources[i].name, p.display()),
+            Err(_) => path.display().to_string(),
+        }
+    }
+
+    fn get_source_and_path(&self, path: &Path) -> Result<(usize, PathBuf)> {
+        let mut components = path
+            .components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::CurDir));
+        let index = match components.next() {
+            Some(Component::Normal(name)) => name.to_string_lossy().parse::<usize>().ok(),
+            _ => None,
+        };
+        match index {
+            Some(i) if i < self.sources.len() => {
+                let rest: PathBuf = components.collect();
+                Ok((i, self.sources[i].root.join(rest)))
+            }
+            _ => Err(anyhow!("Could not find path ({}) in union", path.display())),
+        }
+    }
+
+    fn is_root(path: &Path) -> bool {
+        path.components()
+            .all(|c| matches!(c, Component::RootDir | Component::CurDir))
+    }
+}
+
+impl Default for UnionFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for UnionFileSystem {
+    type File = UnionFile;
+    type DirEntry = UnionDirEntry;
+
+    fn is_file<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        match self.get_source_and_path(path.as_ref()) {
+            Ok((i, p)) => self.sources[i].fs.is_source_file(&p),
+            Err(_) => false,
+        }
+    }
+
+    fn is_dir<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        if Self::is_root(path.as_ref()) {
+            return true;
+        }
+        match self.get_source_and_path(path.as_ref()) {
+            Ok((i, p)) => self.sources[i].fs.is_source_dir(&p),
+            Err(_) => false,
+        }
+    }
+
+    fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Self::File> {
+        let (i, p) = self.get_source_and_path(path.as_ref())?;
+        let file = self.sources[i].fs.open_source_file(&p)?;
+        Ok(UnionFile { file })
+    }
+
+    fn read_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<Self::DirEntry>> {
+        let requested: Vec<(usize, PathBuf)> = if Self::is_root(path.as_ref()) {
+            self.sources
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (i, s.root.clone()))
+                .collect()
+        } else {
+            vec![self.get_source_and_path(path.as_ref())?]
+        };
+
+        let mut entries = vec![];
+        for (i, dir) in requested {
+            let source = &mut self.sources[i];
+            for (p, file_type) in source.fs.read_source_dir(&dir)? {
+                let relative = get_relative_path(&p, &source.root);
+                entries.push(UnionDirEntry {
+                    path: get_source_path(i).join(relative),
+                    file_type,
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+pub struct UnionFile {
+    file: Box<dyn File>,
+}
+
+impl File for UnionFile {
+    fn len(&mut self) -> Result<u64> {
+        self.file.len()
+    }
+}
+
+impl Read for UnionFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for UnionFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+pub struct UnionDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for UnionDirEntry {
+    fn path(&self) -> Result<PathBuf> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType> {
+        Ok(self.file_type.clone())
+    }
+}
+
+fn add_source_from_path(union: &mut UnionFileSystem, path: &Path, verbose: bool) -> Result<()> {
+    let name = path.display().to_string();
+    let mut os_fs = OsFileSystem;
+
+    if os_fs.is_dir(path) {
+        let root = find_cd_dts_dir(&mut os_fs, path)?.unwrap_or(path.to_path_buf());
+        union.add_source(&name, os_fs, root);
+        return Ok(());
+    }
+
+    let mut file: Box<dyn File> = if is_http_url(path) {
+        Box::new(HttpFile::open(&name)?)
+    } else if os_fs.is_file(path) {
+        Box::new(os_fs.open_file(path)?)
+    } else if is_device_path(path) {
+        Box::new(OsFile::open_device(path)?)
+    } else {
+        return Err(anyhow!("Could not open input {}", path.display()));
+    };
+    match get_file_type(file.as_mut(), path, verbose)? {
+        DetectedFileType::Iso => add_iso_source(union, &name, file, path)?,
+        DetectedFileType::Bin => {
+            add_iso_source(union, &name, Box::new(BinFile::from_file(file)?), path)?
+        }
+        DetectedFileType::VirtualDisk => {
+            let disk = decode_virtual_disk_from_file(file, verbose)?;
+            match decode_hdd_img_from_file(Box::new(disk), verbose)? {
+                Some(fs) => union.add_source(&name, fs, HDD_DATA_DIR),
+                None => {
+                    return Err(anyhow!(
+                        "Could not find DTS content partition in {}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+        DetectedFileType::HddImg => match decode_hdd_img_from_file(file, verbose)? {
+            Some(fs) => union.add_source(&name, fs, HDD_DATA_DIR),
+            None => {
+                return Err(anyhow!(
+                    "Could not find DTS content partition in {}",
+                    path.display()
+                ));
+            }
+        },
+        t => {
+            return Err(anyhow!(
+                "Unsupported file type ({:?}) for union input {}",
+                t,
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn add_iso_source(
+    union: &mut UnionFileSystem,
+    name: &str,
+    file: Box<dyn File>,
+    path: &Path,
+) -> Result<()> {
+    let mut fs = IsoFileSystem::from_file(file)?;
+    let root = find_cd_dts_dir(&mut fs, Path::new("/"))?.ok_or(anyhow!(
+        "This is not a DTS CD ISO file ({})",
+        path.display()
+    ))?;
+    union.add_source(name, fs, root);
+    Ok(())
+}
+
+fn find_cd_dts_dir<FS: FileSystem>(fs: &mut FS, path: &Path) -> Result<Option<PathBuf>> {
+    let entries = fs.read_dir(path)?;
+    let has_exe = entries.iter().any(|e| {
+        e.file_name() == CD_DTS_EXE_NAME && e.file_type().is_ok_and(|t| t == FileType::File)
+    });
+    if !has_exe {
+        return Ok(None);
+    }
+    for e in entries {
+        if e.file_name() == CD_DTS_DIR_NAME && e.file_type()? == FileType::Directory {
+            return Ok(Some(e.path()?));
+        }
+    }
+    Ok(None)
+}
+
+fn get_source_path(index: usize) -> PathBuf {
+    PathBuf::from("/").join(index.to_string())
+}
+
+fn get_relative_path(path: &Path, root: &Path) -> PathBuf {
+    let strip = |p: &Path| -> PathBuf {
+        p.components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+            .collect()
+    };
+    let path = strip(path);
+    let root = strip(root);
+    match path.strip_prefix(&root) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+    }
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(e) => matches!(
+            e.to_string_lossy().to_ascii_lowercase().as_str(),
+            "snd" | "aud" | "aue"
+        ),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file::WritableFileSystem,
+        memfile::MemoryFileSystem,
+        snd::{get_test_header, get_test_snd},
+    };
+
+    fn get_snd(id: u16, reel: u8) -> Vec<u8> {
+        get_test_snd(&get_test_header(id, reel), 1)
+    }
+
+    fn get_source(files: &[(&str, Vec<u8>)]) -> MemoryFileSystem {
+        let mut fs = MemoryFileSystem::new();
+        fs.create_dir_all("/data").unwrap();
+        for (name, data) in files {
+            fs.insert_file(Path::new("/data").join(name), data.clone())
+                .unwrap();
+        }
+        fs
+    }
+
+    #[test]
+    fn test_read_dir_merges_sources() {
+        let mut union = UnionFileSystem::new();
+        union.add_source("a", get_source(&[("r1.snd", get_snd(1, 1))]), "/data");
+        union.add_source("b", get_source(&[("r1.snd", get_snd(1, 2))]), "/data");
+
+        let paths: Vec<PathBuf> = union
+            .read_dir("/")
+            .unwrap()
+            .iter()
+            .map(|e| e.path().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("/0/r1.snd"), PathBuf::from("/1/r1.snd")]
+        );
+        assert!(union.is_file("/1/r1.snd"));
+        let mut file = union.open_file("/1/r1.snd").unwrap();
+        assert_eq!(file.read_exact_bytes_at(1, 78).unwrap(), vec![2]);
+        assert!(union.find_conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let mut union = UnionFileSystem::new();
+        union.add_source(
+            "a",
+            get_source(&[("r1.snd", get_snd(7, 1)), ("r2.snd", get_snd(7, 2))]),
+            "/data",
+        );
+        union.add_source("b", get_source(&[("reel2.snd", get_snd(7, 2))]), "/data");
+
+        let conflicts = union.find_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].id, 7);
+        assert_eq!(conflicts[0].reel, 2);
+        assert_eq!(
+            conflicts[0].paths,
+            vec![PathBuf::from("/0/r2.snd"), PathBuf::from("/1/reel2.snd")]
+        );
+    }
+
+    #[test]
+    fn test_find_conflicts_in_subdirectories() {
+        let mut union = UnionFileSystem::new();
+        union.add_source("a", get_source(&[("r3.snd", get_snd(7, 3))]), "/data");
+        let mut b = get_source(&[]);
+        b.create_dir_all("/data/feature/reels").unwrap();
+        b.insert_file("/data/feature/reels/r3.snd", get_snd(7, 3))
+            .unwrap();
+        union.add_source("b", b, "/data");
+
+        let conflicts = union.find_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].paths,
+            vec![
+                PathBuf::from("/0/r3.snd"),
+                PathBuf::from("/1/feature/reels/r3.snd")
+            ]
+        );
+    }
+}
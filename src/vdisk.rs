@@ -0,0 +1,81 @@
+use anyhow::{Result, anyhow};
+
+use crate::{
+    file::File,
+    qcow2file::{QCOW2_MAGIC, Qcow2Format},
+    vdiskfile::{VirtualDiskFile, VirtualDiskFormat},
+    vhdfile::{VHD_FOOTER_LEN, VhdFormat, check_vhd_footer},
+    vmdkfile::{VMDK_SPARSE_MAGIC, VmdkFormat},
+};
+
+const VDISK_MAGIC_LEN: usize = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+enum VirtualDiskType {
+    Qcow2,
+    Vhd,
+    Vmdk,
+}
+
+/// Only checks the start of the file, fixed VHD disks have no header and are
+/// recognized by is_fixed_vhd_file.
+pub fn get_virtual_disk_confidence(file: &mut dyn File) -> u8 {
+    match get_virtual_disk_type_from_header(file) {
+        Some(_) => 100,
+        None => 0,
+    }
+}
+
+pub fn is_fixed_vhd_file(file: &mut dyn File) -> bool {
+    get_virtual_disk_type_from_footer(file).is_some()
+}
+
+pub fn decode_virtual_disk_from_file(
+    mut file: Box<dyn File>,
+    verbose: bool,
+) -> Result<VirtualDiskFile> {
+    let disk_type = get_virtual_disk_type_from_header(file.as_mut())
+        .or_else(|| get_virtual_disk_type_from_footer(file.as_mut()));
+    let format: Box<dyn VirtualDiskFormat> = match disk_type {
+        Some(VirtualDiskType::Qcow2) => Box::new(Qcow2Format::from_file(file)?),
+        Some(VirtualDiskType::Vmdk) => Box::new(VmdkFormat::from_file(file)?),
+        Some(VirtualDiskType::Vhd) => Box::new(VhdFormat::from_file(file)?),
+        None => return Err(anyhow!("Unknown virtual disk format")),
+    };
+    if verbose {
+        println!(
+            "decode_virtual_disk_from_file: {}, disk size: {} bytes",
+            format.name(),
+            format.disk_len()
+        );
+    }
+    Ok(VirtualDiskFile::new(format))
+}
+
+fn get_virtual_disk_type_from_header(file: &mut dyn File) -> Option<VirtualDiskType> {
+    let bytes = file.read_bytes(VDISK_MAGIC_LEN).ok()?;
+    if bytes.starts_with(&QCOW2_MAGIC) {
+        return Some(VirtualDiskType::Qcow2);
+    }
+    if bytes.starts_with(&VMDK_SPARSE_MAGIC) {
+        return Some(VirtualDiskType::Vmdk);
+    }
+    // Dynamic disks start with a copy of the footer
+    if check_vhd_footer(&bytes) {
+        return Some(VirtualDiskType::Vhd);
+    }
+    None
+}
+
+fn get_virtual_disk_type_from_footer(file: &mut dyn File) -> Option<VirtualDiskType> {
+    let len = file.len().ok()?;
+    if len >= VHD_FOOTER_LEN as u64 {
+        let footer = file
+            .read_exact_bytes_at(VDISK_MAGIC_LEN, len - VHD_FOOTER_LEN as u64)
+            .ok()?;
+        if check_vhd_footer(&footer) {
+            return Some(VirtualDiskType::Vhd);
+        }
+    }
+    None
+}
@@ -0,0 +1,107 @@
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use anyhow::Result;
+
+use crate::file::File;
+
+/// A virtual disk container mapping fixed size blocks of the guest disk to
+/// data stored in the container file.
+pub trait VirtualDiskFormat {
+    fn name(&self) -> &'static str;
+    fn disk_len(&self) -> u64;
+    fn block_len(&self) -> u64;
+
+    /// Returns the contents of the block, unallocated blocks are read as zeros.
+    fn read_block(&mut self, index: u64) -> Result<Vec<u8>>;
+}
+
+/// Raw disk view of a virtual disk container.
+pub struct VirtualDiskFile {
+    format: Box<dyn VirtualDiskFormat>,
+    current: u64,
+    cache: Option<(u64, Vec<u8>)>,
+}
+
+impl VirtualDiskFile {
+    pub fn new(format: Box<dyn VirtualDiskFormat>) -> Self {
+        VirtualDiskFile {
+            format,
+            current: 0,
+            cache: None,
+        }
+    }
+
+    pub fn format_name(&self) -> &'static str {
+        self.format.name()
+    }
+
+    fn get_block(&mut self, index: u64) -> std::io::Result<&[u8]> {
+        let cached = matches!(&self.cache, Some((i, _)) if *i == index);
+        if !cached {
+            let block = self
+                .format
+                .read_block(index)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            self.cache = Some((index, block));
+        }
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+impl File for VirtualDiskFile {
+    fn len(&mut self) -> Result<u64> {
+        Ok(self.format.disk_len())
+    }
+}
+
+impl Read for VirtualDiskFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.format.disk_len();
+        if self.current >= len || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_len = self.format.block_len();
+        let index = self.current / block_len;
+        let offset = (self.current % block_len) as usize;
+        let remaining = (len - self.current) as usize;
+        let block = self.get_block(index)?;
+        let count = buf.len().min(block.len() - offset).min(remaining);
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        self.current += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for VirtualDiskFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_current = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(from_end) => self.format.disk_len() as i64 + from_end,
+            SeekFrom::Current(from_current) => self.current as i64 + from_current,
+        };
+        if new_current < 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "trying to seek before start of file",
+            ));
+        }
+        self.current = new_current as u64;
+        Ok(self.current)
+    }
+}
+
+pub fn read_be_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+pub fn read_be_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+pub fn read_le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+pub fn read_le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
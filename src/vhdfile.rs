@@ -0,0 +1,220 @@
+use anyhow::{Result, anyhow};
+
+use crate::{
+    file::File,
+    vdiskfile::{VirtualDiskFormat, read_be_u32, read_be_u64},
+};
+
+pub const VHD_FOOTER_COOKIE: &[u8; 8] = b"conectix";
+const VHD_DYNAMIC_COOKIE: &[u8; 8] = b"cxsparse";
+pub const VHD_FOOTER_LEN: usize = 512;
+const VHD_DYNAMIC_HEADER_LEN: usize = 1024;
+
+const VHD_TYPE_FIXED: u32 = 2;
+const VHD_TYPE_DYNAMIC: u32 = 3;
+const VHD_TYPE_DIFFERENCING: u32 = 4;
+
+const VHD_UNALLOCATED_BLOCK: u32 = 0xFFFF_FFFF;
+const SECTOR_LEN: u64 = 512;
+const FIXED_BLOCK_LEN: u64 = 64 * 1024;
+
+enum VhdLayout {
+    Fixed,
+    Dynamic {
+        block_len: u64,
+        bitmap_len: u64,
+        bat: Vec<u32>,
+    },
+}
+
+pub struct VhdFormat {
+    file: Box<dyn File>,
+    size: u64,
+    layout: VhdLayout,
+}
+
+pub fn check_vhd_footer(bytes: &[u8]) -> bool {
+    bytes.starts_with(VHD_FOOTER_COOKIE)
+}
+
+impl VhdFormat {
+    pub fn from_file(mut file: Box<dyn File>) -> Result<Self> {
+        let len = file.len()?;
+        if len < VHD_FOOTER_LEN as u64 {
+            return Err(anyhow!("Not a VHD file"));
+        }
+        // Older tools wrote a 511 bytes footer, the copy at the start of
+        // dynamic disks is used as a fallback
+        let mut footer = file.read_exact_bytes_at(VHD_FOOTER_LEN, len - VHD_FOOTER_LEN as u64)?;
+        if !check_vhd_footer(&footer) {
+            footer = file.read_bytes(VHD_FOOTER_LEN)?;
+            if !check_vhd_footer(&footer) {
+                return Err(anyhow!("Not a VHD file"));
+            }
+        }
+        let size = read_be_u64(&footer, 48);
+        let layout = match read_be_u32(&footer, 60) {
+            VHD_TYPE_FIXED => VhdLayout::Fixed,
+            VHD_TYPE_DYNAMIC => {
+                let header_offset = read_be_u64(&footer, 16);
+                let header = file.read_exact_bytes_at(VHD_DYNAMIC_HEADER_LEN, header_offset)?;
+                if !header.starts_with(VHD_DYNAMIC_COOKIE) {
+                    return Err(anyhow!("Invalid VHD dynamic disk header"));
+                }
+                let table_offset = read_be_u64(&header, 16);
+                let entries = read_be_u32(&header, 28) as usize;
+                let block_len = read_be_u32(&header, 32) as u64;
+                if block_len == 0 || !block_len.is_multiple_of(SECTOR_LEN) {
+                    return Err(anyhow!("Invalid VHD block size: {}", block_len));
+                }
+                let bat_bytes = file.read_exact_bytes_at(entries * 4, table_offset)?;
+                let bat = (0..entries)
+                    .map(|i| read_be_u32(&bat_bytes, i * 4))
+                    .collect();
+                let bitmap_bytes = (block_len / SECTOR_LEN).div_ceil(8);
+                VhdLayout::Dynamic {
+                    block_len,
+                    bitmap_len: bitmap_bytes.div_ceil(SECTOR_LEN) * SECTOR_LEN,
+                    bat,
+                }
+            }
+            VHD_TYPE_DIFFERENCING => {
+                return Err(anyhow!("Differencing VHD files are not supported"));
+            }
+            t => return Err(anyhow!("Unknown VHD disk type: {}", t)),
+        };
+        Ok(VhdFormat { file, size, layout })
+    }
+}
+
+impl VirtualDiskFormat for VhdFormat {
+    fn name(&self) -> &'static str {
+        match self.layout {
+            VhdLayout::Fixed => "VHD (fixed)",
+            VhdLayout::Dynamic { .. } => "VHD (dynamic)",
+        }
+    }
+
+    fn disk_len(&self) -> u64 {
+        self.size
+    }
+
+    fn block_len(&self) -> u64 {
+        match self.layout {
+            VhdLayout::Fixed => FIXED_BLOCK_LEN,
+            VhdLayout::Dynamic { block_len, .. } => block_len,
+        }
+    }
+
+    fn read_block(&mut self, index: u64) -> Result<Vec<u8>> {
+        let start = index * self.block_len();
+        let len = self.block_len().min(self.size.saturating_sub(start));
+        match &self.layout {
+            VhdLayout::Fixed => self.file.read_exact_bytes_at(len as usize, start),
+            VhdLayout::Dynamic {
+                block_len,
+                bitmap_len,
+                bat,
+            } => {
+                let mut block = vec![0; *block_len as usize];
+                let entry = match bat.get(index as usize) {
+                    Some(e) if *e != VHD_UNALLOCATED_BLOCK => *e as u64,
+                    _ => return Ok(block),
+                };
+                let bitmap_offset = entry * SECTOR_LEN;
+                let bitmap = self
+                    .file
+                    .read_exact_bytes_at(*bitmap_len as usize, bitmap_offset)?;
+                let data = self
+                    .file
+                    .read_exact_bytes_at(*block_len as usize, bitmap_offset + bitmap_len)?;
+                // Sectors not marked in the bitmap were never written
+                for sector in 0..(*block_len / SECTOR_LEN) as usize {
+                    if bitmap[sector / 8] & (0x80 >> (sector % 8)) != 0 {
+                        let range =
+                            sector * SECTOR_LEN as usize..(sector + 1) * SECTOR_LEN as usize;
+                        block[range.clone()].copy_from_slice(&data[range]);
+                    }
+                }
+                Ok(block)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{memfile::MemoryFile, vdiskfile::VirtualDiskFile};
+
+    fn build_footer(size: u64, disk_type: u32, data_offset: u64) -> Vec<u8> {
+        let mut footer = vec![0; VHD_FOOTER_LEN];
+        footer[0..8].copy_from_slice(VHD_FOOTER_COOKIE);
+        footer[16..24].copy_from_slice(&data_offset.to_be_bytes());
+        footer[40..48].copy_from_slice(&size.to_be_bytes());
+        footer[48..56].copy_from_slice(&size.to_be_bytes());
+        footer[60..64].copy_from_slice(&disk_type.to_be_bytes());
+        footer
+    }
+
+    fn get_disk(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 241) as u8).collect()
+    }
+
+    #[test]
+    fn test_fixed() {
+        let disk = get_disk(4096);
+        let mut image = disk.clone();
+        image.extend(build_footer(disk.len() as u64, VHD_TYPE_FIXED, u64::MAX));
+
+        let format = VhdFormat::from_file(Box::new(MemoryFile::from(image))).unwrap();
+        let mut file = VirtualDiskFile::new(Box::new(format));
+        let mut read = vec![];
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read, disk);
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let block_len = 4096usize;
+        let disk = get_disk(block_len * 3);
+
+        let mut image = build_footer(disk.len() as u64, VHD_TYPE_DYNAMIC, 512);
+        let mut header = vec![0; VHD_DYNAMIC_HEADER_LEN];
+        header[0..8].copy_from_slice(VHD_DYNAMIC_COOKIE);
+        header[16..24].copy_from_slice(&1536u64.to_be_bytes());
+        header[28..32].copy_from_slice(&3u32.to_be_bytes());
+        header[32..36].copy_from_slice(&(block_len as u32).to_be_bytes());
+        image.extend(header);
+
+        // Block 0 fully written, block 1 unallocated, block 2 with sector 0 only
+        let mut bat = vec![0; 512];
+        bat[0..4].copy_from_slice(&4u32.to_be_bytes());
+        bat[4..8].copy_from_slice(&VHD_UNALLOCATED_BLOCK.to_be_bytes());
+        bat[8..12].copy_from_slice(&13u32.to_be_bytes());
+        image.extend(bat);
+        let mut bitmap = vec![0; 512];
+        bitmap[0] = 0xFF;
+        image.extend(&bitmap);
+        image.extend(&disk[0..block_len]);
+        bitmap[0] = 0x80;
+        image.extend(&bitmap);
+        image.extend(&disk[2 * block_len..3 * block_len]);
+        image.extend(build_footer(disk.len() as u64, VHD_TYPE_DYNAMIC, 512));
+
+        let format = VhdFormat::from_file(Box::new(MemoryFile::from(image))).unwrap();
+        let mut file = VirtualDiskFile::new(Box::new(format));
+        let mut read = vec![];
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read.len(), disk.len());
+        assert_eq!(read[..block_len], disk[..block_len]);
+        assert!(read[block_len..2 * block_len].iter().all(|b| *b == 0));
+        assert_eq!(
+            read[2 * block_len..2 * block_len + 512],
+            disk[2 * block_len..2 * block_len + 512]
+        );
+        assert!(read[2 * block_len + 512..].iter().all(|b| *b == 0));
+    }
+}
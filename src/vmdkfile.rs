@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    file::File,
+    vdiskfile::{VirtualDiskFormat, read_le_u32, read_le_u64},
+};
+
+pub const VMDK_SPARSE_MAGIC: [u8; 4] = [b'K', b'D', b'M', b'V'];
+const VMDK_HEADER_LEN: usize = 512;
+
+const VMDK_FLAG_COMPRESSED: u32 = 1 << 16;
+const VMDK_GD_AT_END: u64 = u64::MAX;
+const VMDK_ZERO_GRAIN: u32 = 1;
+
+const SECTOR_LEN: u64 = 512;
+
+/// Monolithic sparse VMDK (single extent with the descriptor embedded).
+pub struct VmdkFormat {
+    file: Box<dyn File>,
+    capacity: u64,
+    grain_len: u64,
+    entries_per_table: u64,
+    directory: Vec<u32>,
+    tables: HashMap<u32, Vec<u32>>,
+}
+
+impl VmdkFormat {
+    pub fn from_file(mut file: Box<dyn File>) -> Result<Self> {
+        let header = file.read_bytes(VMDK_HEADER_LEN)?;
+        if !header.starts_with(&VMDK_SPARSE_MAGIC) {
+            return Err(anyhow!("Not a sparse VMDK file"));
+        }
+        let flags = read_le_u32(&header, 8);
+        let gd_offset = read_le_u64(&header, 56);
+        if flags & VMDK_FLAG_COMPRESSED != 0 || gd_offset == VMDK_GD_AT_END {
+            return Err(anyhow!(
+                "Stream optimized VMDK files are not supported, only monolithic sparse"
+            ));
+        }
+        let capacity = read_le_u64(&header, 12) * SECTOR_LEN;
+        let grain_len = read_le_u64(&header, 20) * SECTOR_LEN;
+        let entries_per_table = read_le_u32(&header, 44) as u64;
+        if grain_len == 0 || entries_per_table == 0 {
+            return Err(anyhow!("Invalid VMDK grain size or grain table size"));
+        }
+        let directory_len = capacity.div_ceil(grain_len * entries_per_table) as usize;
+        let directory_bytes =
+            file.read_exact_bytes_at(directory_len * 4, gd_offset * SECTOR_LEN)?;
+        let directory = (0..directory_len)
+            .map(|i| read_le_u32(&directory_bytes, i * 4))
+            .collect();
+
+        Ok(VmdkFormat {
+            file,
+            capacity,
+            grain_len,
+            entries_per_table,
+            directory,
+            tables: HashMap::new(),
+        })
+    }
+
+    fn get_grain_entry(&mut self, index: u64) -> Result<u32> {
+        let table_sector = match self
+            .directory
+            .get((index / self.entries_per_table) as usize)
+        {
+            Some(s) => *s,
+            None => return Ok(0),
+        };
+        if table_sector == 0 {
+            return Ok(0);
+        }
+        if !self.tables.contains_key(&table_sector) {
+            let bytes = self.file.read_exact_bytes_at(
+                self.entries_per_table as usize * 4,
+                table_sector as u64 * SECTOR_LEN,
+            )?;
+            let table = (0..self.entries_per_table as usize)
+                .map(|i| read_le_u32(&bytes, i * 4))
+                .collect();
+            self.tables.insert(table_sector, table);
+        }
+        Ok(self.tables[&table_sector][(index % self.entries_per_table) as usize])
+    }
+}
+
+impl VirtualDiskFormat for VmdkFormat {
+    fn name(&self) -> &'static str {
+        "VMDK (monolithic sparse)"
+    }
+
+    fn disk_len(&self) -> u64 {
+        self.capacity
+    }
+
+    fn block_len(&self) -> u64 {
+        self.grain_len
+    }
+
+    fn read_block(&mut self, index: u64) -> Result<Vec<u8>> {
+        match self.get_grain_entry(index)? {
+            0 | VMDK_ZERO_GRAIN => Ok(vec![0; self.grain_len as usize]),
+            sector => self
+                .file
+                .read_exact_bytes_at(self.grain_len as usize, sector as u64 * SECTOR_LEN),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{memfile::MemoryFile, vdiskfile::VirtualDiskFile};
+
+    #[test]
+    fn test_sparse() {
+        // 4 grains of 2 sectors, 2 entries per grain table
+        let grain_len = 1024usize;
+        let mut image = vec![0; VMDK_HEADER_LEN];
+        image[0..4].copy_from_slice(&VMDK_SPARSE_MAGIC);
+        image[4..8].copy_from_slice(&1u32.to_le_bytes());
+        image[12..20].copy_from_slice(&8u64.to_le_bytes());
+        image[20..28].copy_from_slice(&2u64.to_le_bytes());
+        image[44..48].copy_from_slice(&2u32.to_le_bytes());
+        image[56..64].copy_from_slice(&1u64.to_le_bytes());
+
+        // Grain directory at sector 1, grain tables at sectors 2 and 3
+        let mut directory = vec![0; 512];
+        directory[0..4].copy_from_slice(&2u32.to_le_bytes());
+        directory[4..8].copy_from_slice(&3u32.to_le_bytes());
+        image.extend(directory);
+        let mut table0 = vec![0; 512];
+        table0[0..4].copy_from_slice(&4u32.to_le_bytes());
+        image.extend(table0);
+        let mut table1 = vec![0; 512];
+        table1[4..8].copy_from_slice(&6u32.to_le_bytes());
+        image.extend(table1);
+        let grain0: Vec<u8> = (0..grain_len).map(|i| i as u8).collect();
+        let grain3: Vec<u8> = (0..grain_len).map(|i| (i / 4) as u8).collect();
+        image.extend(&grain0);
+        image.extend(&grain3);
+
+        let format = VmdkFormat::from_file(Box::new(MemoryFile::from(image))).unwrap();
+        let mut file = VirtualDiskFile::new(Box::new(format));
+        let mut read = vec![];
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read.len(), 4 * grain_len);
+        assert_eq!(read[..grain_len], grain0[..]);
+        assert!(read[grain_len..3 * grain_len].iter().all(|b| *b == 0));
+        assert_eq!(read[3 * grain_len..], grain3[..]);
+    }
+}